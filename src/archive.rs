@@ -0,0 +1,165 @@
+//! This module deals with "archive:" sources (tarballs and zip files).
+//!
+//! Archives are downloaded into a cache directory, keyed by their SHA-256
+//! checksum, and verified before they get extracted.
+
+use std::io::Read;
+use std::process::Command;
+use std::sync::OnceLock;
+
+use anyhow::{anyhow, Context as _, Result};
+use camino::{Utf8Path, Utf8PathBuf};
+use log::info;
+use sha2::{Digest, Sha256};
+
+use crate::download::Archive;
+use crate::offline;
+
+static CACHE_DIR: OnceLock<Utf8PathBuf> = OnceLock::new();
+
+pub fn set_cache_dir(cache_dir: Utf8PathBuf) {
+    CACHE_DIR.set(cache_dir).ok();
+}
+
+fn sha256_of(path: &Utf8Path) -> Result<String> {
+    let mut file = std::fs::File::open(path).with_context(|| format!("opening {path}"))?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher).with_context(|| format!("reading {path}"))?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Returns whether `path` is a zip file, going by its first bytes (as the url
+/// might not end in `.zip`).
+fn is_zip(path: &Utf8Path) -> Result<bool> {
+    let mut file = std::fs::File::open(path).with_context(|| format!("opening {path}"))?;
+    let mut magic = [0u8; 4];
+    match file.read_exact(&mut magic) {
+        Ok(()) => Ok(matches!(&magic, b"PK\x03\x04" | b"PK\x05\x06")),
+        // too short for a zip file
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e).with_context(|| format!("reading {path}")),
+    }
+}
+
+fn run(command: &mut Command) -> Result<()> {
+    let status = command
+        .status()
+        .with_context(|| format!("running {:?}", command.get_program()))?;
+    if !status.success() {
+        return Err(anyhow!("{:?} failed ({status})", command.get_program()));
+    }
+    Ok(())
+}
+
+/// Moves the contents of `dir` into `target`, leaving out the first `strip`
+/// path components (like `tar --strip-components`).
+fn move_stripped(dir: &Utf8Path, target: &Utf8Path, strip: usize) -> Result<()> {
+    for entry in dir.read_dir_utf8()? {
+        let entry = entry?;
+        if strip == 0 {
+            let dest = target.join(entry.file_name());
+            std::fs::rename(entry.path(), &dest).with_context(|| format!("creating {dest}"))?;
+        } else if entry.file_type()?.is_dir() {
+            move_stripped(entry.path(), target, strip - 1)?;
+        }
+    }
+    Ok(())
+}
+
+impl Archive {
+    /// Returns the file name of the archive (the last path component of its url).
+    pub fn file_name(&self) -> &str {
+        self.url.rsplit('/').next().unwrap()
+    }
+
+    fn cache_path(&self) -> Result<Utf8PathBuf> {
+        let cache_dir = CACHE_DIR
+            .get()
+            .ok_or(anyhow!("archive cache not available"))?;
+        Ok(cache_dir
+            .join(self.sha256.to_lowercase())
+            .join(self.file_name()))
+    }
+
+    /// Returns whether the archive has already been downloaded.
+    pub fn is_cached(&self) -> bool {
+        self.cache_path().is_ok_and(|path| path.is_file())
+    }
+
+    /// Downloads the archive into the cache (unless it is there already) and
+    /// returns the path of the cached file.
+    fn fetch(&self) -> Result<Utf8PathBuf> {
+        let path = self.cache_path()?;
+        let expected = self.sha256.to_lowercase();
+
+        if path.is_file() {
+            if sha256_of(&path)? == expected {
+                return Ok(path);
+            }
+            std::fs::remove_file(&path).with_context(|| format!("removing {path}"))?;
+        }
+
+        if offline::enabled() {
+            return Err(offline::NotAvailable(format!("archive {}", self.url)).into());
+        }
+
+        let parent = path.parent().unwrap();
+        std::fs::create_dir_all(parent).with_context(|| format!("creating {parent}"))?;
+
+        info!("laze: downloading {}", self.url);
+        let partial = path.with_extension("part");
+        run(Command::new("curl")
+            .arg("--fail")
+            .arg("--silent")
+            .arg("--show-error")
+            .arg("--location")
+            .arg("--output")
+            .arg(&partial)
+            .arg(&self.url))
+        .with_context(|| format!("downloading {}", self.url))?;
+
+        let actual = sha256_of(&partial)?;
+        if actual != expected {
+            let _ = std::fs::remove_file(&partial);
+            return Err(anyhow!(
+                "checksum mismatch for {}: expected sha256 {expected}, got {actual}",
+                self.url
+            ));
+        }
+
+        std::fs::rename(&partial, &path).with_context(|| format!("creating {path}"))?;
+        Ok(path)
+    }
+
+    /// Downloads (if needed), verifies and extracts the archive into `target`.
+    pub fn extract(&self, target: &Utf8Path) -> Result<()> {
+        let archive = self.fetch()?;
+
+        let extract_dir = Utf8PathBuf::from(format!("{target}.extracting"));
+        if extract_dir.exists() {
+            std::fs::remove_dir_all(&extract_dir)
+                .with_context(|| format!("removing {extract_dir}"))?;
+        }
+        std::fs::create_dir_all(&extract_dir).with_context(|| format!("creating {extract_dir}"))?;
+
+        if is_zip(&archive)? {
+            run(Command::new("unzip")
+                .arg("-q")
+                .arg(&archive)
+                .arg("-d")
+                .arg(&extract_dir))
+        } else {
+            // tar detects the compression by itself
+            run(Command::new("tar")
+                .arg("-xf")
+                .arg(&archive)
+                .arg("-C")
+                .arg(&extract_dir))
+        }
+        .with_context(|| format!("extracting {archive}"))?;
+
+        std::fs::create_dir_all(target).with_context(|| format!("creating {target}"))?;
+        move_stripped(&extract_dir, target, self.strip_components)?;
+        std::fs::remove_dir_all(&extract_dir).with_context(|| format!("removing {extract_dir}"))
+    }
+}
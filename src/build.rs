@@ -6,6 +6,7 @@ use indexmap::{IndexMap, IndexSet};
 use itertools::{enumerate, Itertools};
 use log::trace;
 
+use crate::location::Locate;
 use crate::model::{Context, ContextBag, Dependency, Module};
 use crate::nested_env::{self, Env};
 
@@ -47,6 +48,8 @@ struct Resolver<'a> {
     build: &'a Build<'a>,
     state_stack: Vec<ResolverState<'a>>,
     state: ResolverState<'a>,
+    explain: bool,
+    rejected: IndexMap<String, Vec<String>>,
 }
 
 pub struct ResolverResult<'a> {
@@ -54,6 +57,68 @@ pub struct ResolverResult<'a> {
     pub providers: IndexMap<&'a String, Vec<&'a Module>>,
 }
 
+impl<'a> ResolverResult<'a> {
+    /// Returns the module names in link order, where every module comes
+    /// before the modules it selects (reverse post-order over `selects`).
+    ///
+    /// The resolving order is not enough here, as a module that is selected
+    /// early might depend on one that was selected later.
+    pub fn link_order(&self) -> IndexSet<&'a str> {
+        fn visit<'a>(
+            result: &ResolverResult<'a>,
+            module: &'a Module,
+            visited: &mut IndexSet<&'a str>,
+            post_order: &mut Vec<&'a str>,
+        ) {
+            if !visited.insert(&module.name) {
+                return;
+            }
+            for dep in &module.selects {
+                let name = dep.get_name();
+                if let Some(dep_module) = result.modules.get(&name) {
+                    visit(result, dep_module, visited, post_order);
+                }
+                for provider in result.providers.get(&name).into_iter().flatten() {
+                    visit(result, provider, visited, post_order);
+                }
+            }
+            post_order.push(&module.name);
+        }
+
+        let mut visited = IndexSet::new();
+        let mut post_order = Vec::new();
+        for module in self.modules.values() {
+            visit(self, module, &mut visited, &mut post_order);
+        }
+        post_order.into_iter().rev().collect()
+    }
+}
+
+/// Records which module's dependency pulled a module into a build.
+#[derive(Debug, Clone)]
+pub struct SelectedBy<'a> {
+    /// the module that has the dependency
+    pub module: &'a Module,
+    /// the dependency as written (if-then dependencies are recorded once triggered)
+    pub dependency: Dependency<String>,
+    /// whether the dependency was satisfied by a module providing its name
+    pub provided: bool,
+}
+
+/// Result of a dependency resolution, including what led to it.
+///
+/// See [`Build::explain_selects`].
+pub struct Explanation<'a> {
+    /// the resolution result (or the error that made the build fail)
+    pub result: Result<ResolverResult<'a>, Error>,
+    /// for each resolved module, the dependency that selected it
+    pub selected_by: HashMap<&'a String, SelectedBy<'a>>,
+    /// for each module that could not be selected, the reason(s)
+    pub rejected: IndexMap<String, Vec<String>>,
+    /// modules disabled or conflicted in the final state, and by which modules
+    pub disabled: HashMap<String, HashSet<&'a String>>,
+}
+
 #[derive(Debug, Clone, Default)]
 struct ResolverState<'a> {
     module_set: HashSet<&'a String>,
@@ -62,6 +127,7 @@ struct ResolverState<'a> {
     disabled_modules: HashMap<String, HashSet<&'a String>>,
     provided_by: HashMap<&'a String, Vector<&'a Module>>,
     requires: HashMap<String, HashSet<&'a String>>,
+    selected_by: HashMap<&'a String, SelectedBy<'a>>,
 }
 
 impl<'a> Resolver<'a> {
@@ -86,10 +152,30 @@ impl<'a> Resolver<'a> {
                 requires: required_modules_map,
                 ..Default::default()
             },
+            explain: false,
+            rejected: IndexMap::new(),
         }
     }
 
     fn resolve(mut self) -> Result<ResolverResult<'a>, Error> {
+        self.resolve_binary()?;
+        self.result()
+    }
+
+    fn explain(mut self) -> Explanation<'a> {
+        self.explain = true;
+        let result = self.resolve_binary().and_then(|_| self.result());
+        let mut rejected = self.rejected;
+        rejected.retain(|name, _| !self.state.module_set.contains(name));
+        Explanation {
+            result,
+            selected_by: self.state.selected_by,
+            rejected,
+            disabled: self.state.disabled_modules,
+        }
+    }
+
+    fn resolve_binary(&mut self) -> Result<(), Error> {
         self.trace(|| {
             format!(
                 "resolving binary {} for builder {}:",
@@ -97,18 +183,18 @@ impl<'a> Resolver<'a> {
             )
         });
 
-        let (binary, builder) = (&self.build.binary.name, &self.build.builder.name);
+        let build = self.build;
+        let (binary, builder) = (&build.binary.name, &build.builder.name);
 
-        let inner = || {
-            self.resolve_module_deep(&self.build.binary)?;
-            self.check_requires()?;
-            self.result()
+        let mut inner = || {
+            self.resolve_module_deep(&self.build.binary, None)?;
+            self.check_requires()
         };
 
         inner().with_context(|| format!("binary \"{}\" for builder \"{}\"", binary, builder))
     }
 
-    fn result(self) -> Result<ResolverResult<'a>, Error> {
+    fn result(&self) -> Result<ResolverResult<'a>, Error> {
         let modules = self
             .state
             .module_list
@@ -149,17 +235,39 @@ impl<'a> Resolver<'a> {
         res
     }
 
-    fn resolve_module_name_deep(&mut self, module_name: &String) -> Result<(), Error> {
+    fn resolve_module_name_deep(
+        &mut self,
+        module_name: &String,
+        selected_by: Option<SelectedBy<'a>>,
+    ) -> Result<(), Error> {
         let (_context, module) = match self
             .build
             .build_context
             .resolve_module(module_name, self.build.bag)
         {
             Some(x) => x,
-            None => return Err(anyhow!("module \"{}\" not found", module_name)),
+            None => {
+                self.reject(module_name, || "not found".to_string());
+                return Err(anyhow!("module \"{}\" not found", module_name));
+            }
         };
 
-        self.resolve_module_deep(module)
+        self.resolve_module_deep(module, selected_by)
+    }
+
+    /// Remembers why `module_name` could not be selected, for [`Resolver::explain`].
+    fn reject<F>(&mut self, module_name: &str, f: F)
+    where
+        F: FnOnce() -> String,
+    {
+        if !self.explain {
+            return;
+        }
+        let reason = f();
+        let reasons = self.rejected.entry(module_name.to_string()).or_default();
+        if !reasons.contains(&reason) {
+            reasons.push(reason);
+        }
     }
 
     fn trace<F>(&self, f: F)
@@ -169,7 +277,11 @@ impl<'a> Resolver<'a> {
         trace!("{}{}", self.state_indent(), f());
     }
 
-    fn resolve_module_deep(&mut self, module: &'a Module) -> Result<(), Error> {
+    fn resolve_module_deep(
+        &mut self,
+        module: &'a Module,
+        selected_by: Option<SelectedBy<'a>>,
+    ) -> Result<(), Error> {
         self.trace(|| format!("resolving {}", module.name));
         if self.state.module_set.contains(&module.name) {
             self.trace(|| format!("resolving {}: already selected", module.name));
@@ -184,6 +296,9 @@ impl<'a> Resolver<'a> {
                 &format!("disabled/conflicted by {disabled_by}")
             };
             self.trace(|| format!("resolving {}: {msg}", module.name));
+            if !disabled_by.is_empty() {
+                self.reject(&module.name, || msg.to_string());
+            }
             return Err(anyhow!("\"{}\" is {msg}", module.name));
         }
 
@@ -191,6 +306,9 @@ impl<'a> Resolver<'a> {
             for conflicted in conflicts {
                 if self.state.module_set.contains(conflicted) {
                     self.trace(|| format!("resolving {}: conflicts {conflicted}", module.name));
+                    self.reject(&module.name, || {
+                        format!("conflicts \"{conflicted}\", which was already selected")
+                    });
                     return Err(anyhow!("\"{}\" conflicts \"{}\"", module.name, conflicted));
                 }
 
@@ -212,6 +330,12 @@ impl<'a> Resolver<'a> {
                             module.name, others_wrapped.join(", ")
                         )
                     });
+                    self.reject(&module.name, || {
+                        format!(
+                            "conflicts \"{conflicted}\", which is already provided by {}",
+                            others_wrapped.join(", ")
+                        )
+                    });
                     return Err(anyhow!(
                         "\"{}\" conflicts already provided \"{}\" (by {})",
                         module.name,
@@ -240,6 +364,7 @@ impl<'a> Resolver<'a> {
                     };
 
                     self.trace(|| format!("resolving {}: {msg}", module.name));
+                    self.reject(&module.name, || msg.clone());
                     return Err(anyhow!(msg));
                 }
             }
@@ -280,6 +405,9 @@ impl<'a> Resolver<'a> {
 
         self.state.module_set.insert(&module.name);
         self.state.module_list.push_back((&module.name, module));
+        if let Some(selected_by) = selected_by {
+            self.state.selected_by.insert(&module.name, selected_by);
+        }
 
         // late if_then_deps are dependencies that are induced by if_then_deps of
         // other modules.
@@ -328,7 +456,12 @@ impl<'a> Resolver<'a> {
             let mut provided_errors = None;
             if let Some(provided) = &self.build.build_context.provided {
                 if let Some(providing_modules) = provided.get(dep_name) {
-                    let res = self.resolve_module_list(providing_modules, dep_name);
+                    let selected_by = self.explain.then(|| SelectedBy {
+                        module,
+                        dependency: dep.clone(),
+                        provided: true,
+                    });
+                    let res = self.resolve_module_list(providing_modules, dep_name, selected_by);
 
                     if res.is_ok() {
                         self.trace(|| format!("got at least one provider for `{dep_name}`"));
@@ -345,19 +478,30 @@ impl<'a> Resolver<'a> {
                 }
             }
 
-            if let Err(err) = self.resolve_module_name_deep(dep_name) {
+            let selected_by = self.explain.then(|| SelectedBy {
+                module,
+                dependency: dep.clone(),
+                provided: false,
+            });
+            if let Err(err) = self.resolve_module_name_deep(dep_name, selected_by) {
                 self.trace(|| format!("resolving {dep_name}: failed (optional={optional}, was_provided={was_provided})"));
 
                 if optional || was_provided {
                     continue;
                 } else {
                     self.state_pop();
+                    self.reject(&module.name, || format!("cannot resolve \"{dep_name}\""));
+                    let location = module
+                        .location
+                        .as_ref()
+                        .map(|location| location.entry(&["depends", "selects"], dep_name));
                     return if let Some(mut provided_errors) = provided_errors {
                         provided_errors.errors.push(err);
                         Err(provided_errors.into())
                     } else {
                         Err(err)
                     }
+                    .located(location.as_ref())
                     .with_context(|| {
                         format!("\"{}\" cannot resolve \"{}\"", module.name, dep_name)
                     });
@@ -382,6 +526,7 @@ impl<'a> Resolver<'a> {
         &mut self,
         providing_modules: &IndexSet<String>,
         provided_name: &String,
+        selected_by: Option<SelectedBy<'a>>,
     ) -> Result<(), ErrorVec> {
         self.trace(|| format!("resolving provided name {provided_name}"));
 
@@ -408,7 +553,7 @@ impl<'a> Resolver<'a> {
                 }
             }
 
-            let res = self.resolve_module_name_deep(module_name);
+            let res = self.resolve_module_name_deep(module_name, selected_by.clone());
             if let Err(e) = res {
                 errors.push(e);
             } else {
@@ -513,4 +658,22 @@ impl<'a: 'b, 'b> Build<'b> {
     ) -> Result<ResolverResult<'_>, Error> {
         Resolver::new(self, disabled_modules, required_modules).resolve()
     }
+
+    /// Like [`Build::resolve_selects`], but also records why each module was
+    /// selected or rejected.
+    pub fn explain_selects(
+        &self,
+        disabled_modules: IndexSet<String>,
+        required_modules: IndexSet<String>,
+    ) -> Explanation<'_> {
+        Resolver::new(self, disabled_modules, required_modules).explain()
+    }
+
+    pub fn binary(&self) -> &Module {
+        &self.binary
+    }
+
+    pub fn builder(&self) -> &Context {
+        self.builder
+    }
 }
@@ -0,0 +1,221 @@
+//! Configure all builder / app combinations, without build output (`laze check`)
+
+use std::io::Write;
+
+use anyhow::{anyhow, Context as _, Result};
+use camino::Utf8Path;
+use indexmap::IndexMap;
+use itertools::Itertools;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    data::load,
+    download::Resolver,
+    generate::{configure_build, laze_env, ConfigureBuildResult, Selector},
+    inspect::BuildSettings,
+    lockfile::Lockfile,
+};
+
+/// Whether (or why not) an app is built for a builder
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum CheckStatus {
+    Built,
+    Skipped { reason: String },
+    Error { error: String },
+}
+
+impl CheckStatus {
+    pub fn is_built(&self) -> bool {
+        matches!(self, CheckStatus::Built)
+    }
+
+    fn reason(&self) -> Option<&str> {
+        match self {
+            CheckStatus::Built => None,
+            CheckStatus::Skipped { reason } => Some(reason),
+            CheckStatus::Error { error } => Some(error),
+        }
+    }
+
+    fn short(&self) -> &'static str {
+        match self {
+            CheckStatus::Built => "yes",
+            CheckStatus::Skipped { .. } => "-",
+            CheckStatus::Error { .. } => "ERROR",
+        }
+    }
+}
+
+impl std::fmt::Display for CheckStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.reason() {
+            Some(reason) => write!(f, "{reason}"),
+            None => write!(f, "built"),
+        }
+    }
+}
+
+/// The outcome of configuring all (selected) builder / app pairs
+#[derive(Deserialize, Serialize, Debug, Default)]
+pub struct CheckResult {
+    builds: IndexMap<String, IndexMap<String, CheckStatus>>,
+}
+
+impl CheckResult {
+    pub fn from_project(
+        project_root: &Utf8Path,
+        project_file: &Utf8Path,
+        build_dir: &Utf8Path,
+        builders: &Selector,
+        apps: &Selector,
+        settings: &BuildSettings,
+    ) -> Result<Self> {
+        let (contexts, _, _) = load(project_file, build_dir)?;
+        // downloads are resolved like for building, but `laze.lock` is left alone
+        let downloads = Resolver::new(Lockfile::load(project_file)?);
+        let laze_env = laze_env(project_root, build_dir);
+
+        let selected_builders = match builders {
+            Selector::All => contexts.builders_vec(),
+            Selector::Some(builders) => contexts.builders_by_name(builders)?,
+        };
+
+        let bins = contexts
+            .modules()
+            .map(|(_, module)| module)
+            .filter(|module| module.is_binary)
+            .collect_vec();
+
+        if let Selector::Some(apps) = apps {
+            let unknown = apps
+                .iter()
+                .filter(|app| !bins.iter().any(|bin| &&bin.name == app))
+                .join(", ");
+            if !unknown.is_empty() {
+                return Err(anyhow!("unknown apps specified: {unknown}"));
+            }
+        }
+
+        let pairs = selected_builders
+            .iter()
+            .cartesian_product(bins.iter().filter(|bin| apps.selects(&bin.name)))
+            .collect_vec();
+
+        let statuses = pairs
+            .par_iter()
+            .map(|(builder, bin)| {
+                match configure_build(
+                    bin,
+                    &contexts,
+                    builder,
+                    &laze_env,
+                    settings.select.as_ref(),
+                    settings.disable.as_ref(),
+                    settings.require.as_ref(),
+                    &settings.define.as_ref(),
+                    false,
+                    &downloads,
+                ) {
+                    Ok(ConfigureBuildResult::Build(..)) => CheckStatus::Built,
+                    Ok(ConfigureBuildResult::NoBuild(reason)) => CheckStatus::Skipped {
+                        reason: reason.to_string(),
+                    },
+                    Err(e) => CheckStatus::Error {
+                        error: format!("{e:#}"),
+                    },
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let mut result = CheckResult::default();
+        for ((builder, bin), status) in pairs.iter().zip(statuses) {
+            result
+                .builds
+                .entry(builder.name.clone())
+                .or_default()
+                .insert(bin.name.clone(), status);
+        }
+
+        Ok(result)
+    }
+
+    pub fn from_file(path: &Utf8Path) -> Result<Self> {
+        let file = std::fs::File::open(path).with_context(|| format!("opening \"{path}\""))?;
+        serde_json::from_reader(std::io::BufReader::new(file))
+            .with_context(|| format!("parsing \"{path}\""))
+    }
+
+    fn get(&self, builder: &str, app: &str) -> Option<&CheckStatus> {
+        self.builds.get(builder).and_then(|apps| apps.get(app))
+    }
+
+    fn iter(&self) -> impl Iterator<Item = (&String, &String, &CheckStatus)> {
+        self.builds.iter().flat_map(|(builder, apps)| {
+            apps.iter().map(move |(app, status)| (builder, app, status))
+        })
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.iter()
+            .any(|(_, _, status)| matches!(status, CheckStatus::Error { .. }))
+    }
+
+    /// Returns all builder / app pairs that are built in `baseline`, but not here.
+    pub fn regressions<'a>(
+        &'a self,
+        baseline: &'a CheckResult,
+    ) -> Vec<(&'a String, &'a String, Option<&'a CheckStatus>)> {
+        baseline
+            .iter()
+            .filter(|(_, _, status)| status.is_built())
+            .map(|(builder, app, _)| (builder, app, self.get(builder, app)))
+            .filter(|(_, _, status)| !status.is_some_and(CheckStatus::is_built))
+            .collect()
+    }
+
+    /// Writes the builder (rows) / app (columns) matrix, followed by the
+    /// reasons for each pair that is not built.
+    pub fn write_text<W: Write>(&self, mut w: W) -> Result<()> {
+        let apps = self
+            .builds
+            .values()
+            .flat_map(|apps| apps.keys())
+            .unique()
+            .collect_vec();
+
+        let first_width = self.builds.keys().map(|b| b.len()).max().unwrap_or(0);
+        // wide enough for the app name and any status
+        let widths = apps.iter().map(|app| app.len().max(5)).collect_vec();
+
+        let mut line = format!("{:first_width$}", "");
+        for (app, width) in apps.iter().zip(&widths) {
+            line.push_str(&format!("  {app:width$}"));
+        }
+        writeln!(w, "{}", line.trim_end())?;
+
+        for (builder, statuses) in &self.builds {
+            let mut line = format!("{builder:first_width$}");
+            for (app, width) in apps.iter().zip(&widths) {
+                let status = statuses.get(*app).map_or("", CheckStatus::short);
+                line.push_str(&format!("  {status:width$}"));
+            }
+            writeln!(w, "{}", line.trim_end())?;
+        }
+
+        let mut not_built = self
+            .iter()
+            .filter_map(|(builder, app, status)| Some((builder, app, status.reason()?)))
+            .peekable();
+
+        if not_built.peek().is_some() {
+            writeln!(w)?;
+            for (builder, app, reason) in not_built {
+                writeln!(w, "{builder} / {app}: {reason}")?;
+            }
+        }
+
+        Ok(())
+    }
+}
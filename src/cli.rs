@@ -73,6 +73,55 @@ pub fn clap() -> clap::Command {
             .action(ArgAction::Append)
     }
 
+    fn builders() -> Arg {
+        Arg::new("builders")
+            .short('b')
+            .long("builders")
+            .help("builders to configure")
+            .env("LAZE_BUILDERS")
+            .action(ArgAction::Append)
+            .value_delimiter(',')
+            .add(ArgValueCandidates::new(builder_completer))
+    }
+
+    fn apps() -> Arg {
+        Arg::new("apps")
+            .short('a')
+            .long("apps")
+            .help("apps to configure")
+            .env("LAZE_APPS")
+            .action(ArgAction::Append)
+            .value_delimiter(',')
+            .add(ArgValueCandidates::new(app_completer))
+    }
+
+    fn builder() -> Arg {
+        Arg::new("builder")
+            .short('b')
+            .long("builder")
+            .help("builder to inspect")
+            .required(true)
+            .num_args(1)
+            .add(ArgValueCandidates::new(builder_completer))
+    }
+
+    fn app() -> Arg {
+        Arg::new("app")
+            .short('a')
+            .long("app")
+            .help("app to inspect")
+            .required(true)
+            .num_args(1)
+            .add(ArgValueCandidates::new(app_completer))
+    }
+
+    fn json() -> Arg {
+        Arg::new("json")
+            .long("json")
+            .help("output as JSON")
+            .action(ArgAction::SetTrue)
+    }
+
     fn partition() -> Arg {
         use std::str::FromStr;
         use task_partitioner::PartitionerBuilder;
@@ -128,7 +177,33 @@ pub fn clap() -> clap::Command {
                 .env("LAZE_GLOBAL")
                 .action(ArgAction::SetTrue),
         )
+        .arg(
+            Arg::new("locked")
+                .long("locked")
+                .help("fail if laze.lock is missing or out of date")
+                .global(true)
+                .env("LAZE_LOCKED")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("offline")
+                .long("offline")
+                .help("do not fetch anything, use only the git cache and build directory")
+                .global(true)
+                .env("LAZE_OFFLINE")
+                .action(ArgAction::SetTrue),
+        )
         .arg(git_cache::clap_git_cache_dir_arg())
+        .arg(
+            Arg::new("archive_cache_dir")
+                .long("archive-cache-dir")
+                .help("archive cache base directory")
+                .default_value("~/.cache/laze/archives")
+                .value_parser(clap::value_parser!(Utf8PathBuf))
+                .value_hint(ValueHint::DirPath)
+                .env("LAZE_ARCHIVE_CACHE_DIR")
+                .num_args(1),
+        )
         .subcommand(
             Command::new("build")
                 .about("generate build files and build")
@@ -181,25 +256,15 @@ pub fn clap() -> clap::Command {
                 )
                 .arg(jobs())
                 .next_help_heading("What to build")
+                .arg(builders())
+                .arg(apps())
                 .arg(
-                    Arg::new("builders")
-                        .short('b')
-                        .long("builders")
-                        .help("builders to configure")
-                        .env("LAZE_BUILDERS")
-                        .action(ArgAction::Append)
-                        .value_delimiter(',')
-                        .add(ArgValueCandidates::new(builder_completer)),
-                )
-                .arg(
-                    Arg::new("apps")
-                        .short('a')
-                        .long("apps")
-                        .help("apps to configure")
-                        .env("LAZE_APPS")
+                    Arg::new("artifacts")
+                        .long("artifacts")
+                        .help("only build these artifacts (e.g., `hex`) of the selected apps")
+                        .env("LAZE_ARTIFACTS")
                         .action(ArgAction::Append)
-                        .value_delimiter(',')
-                        .add(ArgValueCandidates::new(app_completer)),
+                        .value_delimiter(','),
                 )
                 .arg(partition())
                 .next_help_heading("Extra build settings")
@@ -221,6 +286,115 @@ pub fn clap() -> clap::Command {
                             .help("output a tree of the configuration builders")
                             .action(ArgAction::SetTrue),
                     ),
+                )
+                .subcommand(
+                    Command::new("modules")
+                        .about("list modules and their relationships")
+                        .arg(
+                            Arg::new("context")
+                                .short('c')
+                                .long("context")
+                                .help("only list modules defined in this context")
+                                .num_args(1),
+                        )
+                        .arg(json()),
+                )
+                .subcommand(
+                    Command::new("why")
+                        .about("explain why a module is (or is not) part of a build")
+                        .arg(
+                            Arg::new("module")
+                                .help("module to explain")
+                                .required(true)
+                                .add(ArgValueCandidates::new(module_completer)),
+                        )
+                        .arg(builder())
+                        .arg(app())
+                        .next_help_heading("Extra build settings")
+                        .arg(select())
+                        .arg(disable())
+                        .arg(require()),
+                )
+                .subcommand(
+                    Command::new("env")
+                        .about("print the variables of a build, or of one of its modules")
+                        .arg(builder())
+                        .arg(app())
+                        .arg(
+                            Arg::new("module")
+                                .short('m')
+                                .long("module")
+                                .help("print the variables of this module")
+                                .num_args(1)
+                                .add(ArgValueCandidates::new(module_completer)),
+                        )
+                        .arg(
+                            Arg::new("origin")
+                                .long("origin")
+                                .help("show where each value was set")
+                                .action(ArgAction::SetTrue),
+                        )
+                        .arg(json())
+                        .next_help_heading("Extra build settings")
+                        .arg(select())
+                        .arg(disable())
+                        .arg(require())
+                        .arg(define()),
+                )
+                .subcommand(
+                    Command::new("graph")
+                        .about("print the module dependency graph of a build")
+                        .arg(builder())
+                        .arg(app())
+                        .arg(
+                            Arg::new("format")
+                                .short('f')
+                                .long("format")
+                                .help("output format")
+                                .num_args(1)
+                                .value_parser(["dot", "mermaid", "json"])
+                                .default_value("dot"),
+                        )
+                        .next_help_heading("Extra build settings")
+                        .arg(select())
+                        .arg(disable())
+                        .arg(require()),
+                ),
+        )
+        .subcommand(
+            Command::new("check")
+                .about("check which apps can be built for which builders")
+                .arg(build_dir())
+                .arg(json())
+                .arg(
+                    Arg::new("baseline")
+                        .long("baseline")
+                        .help("fail if an app that is built in this (JSON) baseline is not built anymore")
+                        .num_args(1)
+                        .value_name("FILE")
+                        .value_parser(clap::value_parser!(Utf8PathBuf))
+                        .value_hint(ValueHint::FilePath),
+                )
+                .next_help_heading("What to check")
+                .arg(builders())
+                .arg(apps())
+                .next_help_heading("Extra build settings")
+                .arg(select())
+                .arg(disable())
+                .arg(require())
+                .arg(define()),
+        )
+        .subcommand(
+            Command::new("lint")
+                .about("check laze files for mistakes")
+                .arg(build_dir())
+                .arg(json())
+                .arg(
+                    Arg::new("deny-warnings")
+                        .short('W')
+                        .long("deny-warnings")
+                        .help("fail on warnings, too")
+                        .action(ArgAction::SetTrue),
                 ),
         )
         .subcommand(
@@ -237,6 +411,16 @@ pub fn clap() -> clap::Command {
                         .action(ArgAction::SetTrue),
                 ),
         )
+        .subcommand(
+            Command::new("update")
+                .about("update the commits of git imports in laze.lock")
+                .arg(build_dir())
+                .arg(
+                    Arg::new("name")
+                        .help("imports to update (default: all)")
+                        .action(ArgAction::Append),
+                ),
+        )
         .subcommand(
             Command::new("new")
                 .about("Create a new laze project at <PATH>")
@@ -258,6 +442,9 @@ pub fn clap() -> clap::Command {
                         .num_args(1),
                 ),
         )
+        .subcommand(
+            Command::new("schema").about("print the JSON schema of laze files (for editors)"),
+        )
         .subcommand(
             Command::new("completion")
                 .about("Generate laze shell completions.")
@@ -283,4 +470,32 @@ pub fn clap() -> clap::Command {
                 .hide(true),
         )
         .subcommand(git_cache::clap_clone_command("git-clone").hide(true))
+        .subcommand(
+            Command::new("download-archive")
+                .about("download, verify and extract an archive")
+                .hide(true)
+                .arg(Arg::new("url").help("archive url").required(true))
+                .arg(
+                    Arg::new("target_path")
+                        .help("target path")
+                        .required(true)
+                        .value_parser(clap::value_parser!(Utf8PathBuf))
+                        .value_hint(ValueHint::DirPath),
+                )
+                .arg(
+                    Arg::new("sha256")
+                        .long("sha256")
+                        .help("expected SHA-256 checksum")
+                        .required(true)
+                        .num_args(1),
+                )
+                .arg(
+                    Arg::new("strip-components")
+                        .long("strip-components")
+                        .help("strip this many leading path components")
+                        .default_value("0")
+                        .value_parser(clap::value_parser!(usize))
+                        .num_args(1),
+                ),
+        )
 }
@@ -11,21 +11,28 @@ use std::collections::{HashMap, HashSet};
 use std::fs::read_to_string;
 use std::time::{Duration, Instant};
 
-use anyhow::{Context as _, Error, Result};
+use anyhow::{anyhow, Context as _, Error, Result};
 use camino::{Utf8Path, Utf8PathBuf};
+use schemars::JsonSchema;
 use semver::Version;
 use serde::{Deserialize, Deserializer, Serialize};
 
 use treestate::{FileState, TreeState};
 
-use super::download::Download;
-use super::model::CustomBuild;
-use super::nested_env::{Env, EnvKey, MergeOption};
-use super::{Context, ContextBag, Dependency, Module, Rule, Task};
+use super::download::{Download, Source};
+use super::model::{CustomBuild, SourceOverride};
+use super::nested_env::{self, Env, EnvKey, IfMissing, MergeOption};
+use super::{Context, ContextBag, ContextBagError, Dependency, Module, Rule, Task};
+use crate::location::{self, Locate, Location};
+use crate::lockfile::Lockfile;
+use crate::offline;
 use crate::serde_bool_helpers::{default_as_false, default_as_true};
-use crate::utils::{StringOrMapString, StringOrMapVecString};
+use crate::utils::{Conditional, StringOrConditional, StringOrMapString, StringOrMapVecString};
 
+mod conditions;
+mod globs;
 mod import;
+use conditions::Conditions;
 use import::ImportEntry;
 
 pub type FileTreeState = TreeState<FileState, std::path::PathBuf>;
@@ -76,19 +83,30 @@ where
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// A laze file (`laze-project.yml`, `laze.yml`, `laze-lib.yml`)
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
+#[schemars(title = "laze file")]
 struct YamlFile {
+    /// List of contexts.
     contexts: Option<Vec<YamlContext>>,
+    /// List of builders (contexts that apps can be built for).
     builders: Option<Vec<YamlContext>>,
+    /// List of modules.
     #[serde(default, deserialize_with = "deserialize_some")]
     modules: Option<Option<Vec<YamlModule>>>,
+    /// List of apps (binaries that can be built for builders).
     #[serde(default, deserialize_with = "deserialize_some")]
     apps: Option<Option<Vec<YamlModule>>>,
+    /// List of local or remote sources for additional laze projects.
     imports: Option<Vec<ImportEntry>>,
-    includes: Option<Vec<String>>,
+    /// List of YAML files to include, relative to this file. `{ if: <condition>, then: [file] }` only includes a file if the condition is `true`.
+    includes: Option<Vec<StringOrConditional>>,
+    /// List of directories to parse `<directory>/laze.yml` in.
     subdirs: Option<Vec<String>>,
-    defaults: Option<HashMap<String, YamlModule>>,
+    /// Default properties for the modules, apps, contexts and builders of this file and its subdirectories.
+    defaults: Option<YamlDefaults>,
+    /// Minimum laze version (semver) needed to read this file.
     #[serde(default, deserialize_with = "deserialize_version_checked")]
     laze_required_version: Option<Version>,
     #[serde(skip)]
@@ -99,7 +117,11 @@ struct YamlFile {
     included_by: Option<usize>,
     #[serde(skip)]
     import_root: Option<ImportRoot>,
+    #[serde(skip)]
+    location: Option<Location>,
+    /// Ignored by laze, can contain arbitrary data.
     #[serde(rename = "meta")]
+    #[schemars(with = "Option<serde_json::Value>")]
     _meta: Option<Value>,
 }
 
@@ -121,70 +143,356 @@ where
     Ok(v)
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Default properties, by what they apply to
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+#[schemars(rename = "Defaults")]
+struct YamlDefaults {
+    /// Defaults for modules.
+    module: Option<YamlModule>,
+    /// Defaults for apps.
+    app: Option<YamlModule>,
+    /// Defaults for contexts.
+    context: Option<YamlContextDefaults>,
+    /// Defaults for builders.
+    builder: Option<YamlContextDefaults>,
+}
+
+impl YamlDefaults {
+    fn module(&self, key: &str) -> Option<&YamlModule> {
+        match key {
+            "module" => self.module.as_ref(),
+            "app" => self.app.as_ref(),
+            _ => None,
+        }
+    }
+
+    fn context(&self, key: &str) -> Option<&YamlContextDefaults> {
+        match key {
+            "context" => self.context.as_ref(),
+            "builder" => self.builder.as_ref(),
+            _ => None,
+        }
+    }
+}
+
+/// Default properties for contexts or builders
+#[derive(Debug, Default, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+#[schemars(rename = "ContextDefaults")]
+struct YamlContextDefaults {
+    /// Variables. Lists are extended by a context's own, other values are overridden.
+    env: Option<YamlEnv>,
+    /// Modules that are always selected, in addition to a context's own.
+    selects: Option<Vec<String>>,
+    /// Modules that are disabled, in addition to a context's own.
+    disables: Option<Vec<String>>,
+    /// Modules that must be present, in addition to a context's own.
+    requires: Option<Vec<String>>,
+    /// Build rules. A context's own rules with the same name take precedence.
+    rules: Option<Vec<YamlRule>>,
+    /// How variables are flattened into strings.
+    #[schemars(with = "Option<HashMap<String, MergeOption>>")]
+    var_options: Option<im::HashMap<String, MergeOption>>,
+    /// Whether modules are linked as static libraries, unless a context sets `archive` itself.
+    archive: Option<bool>,
+    /// Named tasks. A context's own tasks with the same name take precedence.
+    tasks: Option<HashMap<String, YamlTask>>,
+}
+
+/// Merges `own` over `defaults`, using `merge` if both are set.
+fn merge_option<T: Clone>(
+    defaults: &Option<T>,
+    own: &Option<T>,
+    merge: impl Fn(&mut T, &T),
+) -> Option<T> {
+    match (defaults, own) {
+        (Some(defaults), Some(own)) => {
+            let mut merged = defaults.clone();
+            merge(&mut merged, own);
+            Some(merged)
+        }
+        _ => own.clone().or_else(|| defaults.clone()),
+    }
+}
+
+/// Merges the variables of `over` into `env`, extending lists and replacing
+/// other values.
+fn merge_yaml_env(env: &mut YamlEnv, over: &YamlEnv) {
+    for (key, value) in over {
+        match (env.get_mut(key), value) {
+            (Some(YamlEnvValue::List(list)), YamlEnvValue::List(more)) => {
+                list.extend(more.iter().cloned());
+            }
+            _ => {
+                env.insert(key.clone(), value.clone());
+            }
+        }
+    }
+}
+
+impl YamlContextDefaults {
+    /// Returns these defaults, merged over (inherited) `defaults`.
+    fn merged_over(&self, defaults: &YamlContextDefaults) -> YamlContextDefaults {
+        let extend = |a: &mut Vec<_>, b: &Vec<_>| a.extend(b.iter().cloned());
+        YamlContextDefaults {
+            env: merge_option(&defaults.env, &self.env, merge_yaml_env),
+            selects: merge_option(&defaults.selects, &self.selects, extend),
+            disables: merge_option(&defaults.disables, &self.disables, extend),
+            requires: merge_option(&defaults.requires, &self.requires, extend),
+            rules: merge_option(&defaults.rules, &self.rules, |a, b| {
+                a.extend(b.iter().cloned())
+            }),
+            var_options: merge_option(&defaults.var_options, &self.var_options, |a, b| {
+                a.extend(b.clone())
+            }),
+            archive: self.archive.or(defaults.archive),
+            tasks: merge_option(&defaults.tasks, &self.tasks, |a, b| a.extend(b.clone())),
+        }
+    }
+}
+
+/// A context or builder
+#[derive(Debug, Default, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
+#[schemars(rename = "Context", transform = YamlContext::schema_aliases)]
 struct YamlContext {
+    /// Name of this context.
     name: String,
+    /// The parent of this context. Defaults to `default`.
     parent: Option<String>,
+    /// Help text for this context.
     help: Option<String>,
-    env: Option<Env>,
+    /// Variables of this context (and its children).
+    env: Option<YamlEnv>,
+    /// Modules that are always selected for builds in this context or any of its children.
     selects: Option<Vec<String>>,
+    /// Modules that are disabled for builds in this context or any of its children.
     disables: Option<Vec<String>>,
+    /// Virtual module names that this context provides.
     provides: Option<Vec<String>>,
+    /// Virtual module names that this context uniquely provides.
     provides_unique: Option<Vec<String>>,
+    /// Modules that must be present when this context is used.
     requires: Option<Vec<String>>,
+    /// Build rules of this context (and its children).
     rules: Option<Vec<YamlRule>>,
+    /// How variables are flattened into strings.
+    #[schemars(with = "Option<HashMap<String, MergeOption>>")]
     var_options: Option<im::HashMap<String, MergeOption>>,
+    /// Whether modules built for this context (or its children) are linked as static libraries, unless they set `archive` themselves.
+    archive: Option<bool>,
+    /// Named tasks.
     tasks: Option<HashMap<String, YamlTask>>,
+    /// Whether apps can be built for this context. Defaults to `false` (`true` for builders).
     #[serde(default = "default_as_false", alias = "buildable")]
     is_builder: bool,
+    /// Dimensions of variants of this builder (e.g., build type). Each combination of their values becomes a builder named `<name>:<value>:...`.
+    variants: Option<Vec<YamlVariantDimension>>,
+    #[serde(skip)]
+    _is_variant: bool,
+    /// Ignored by laze, can contain arbitrary data.
     #[serde(rename = "meta")]
+    #[schemars(with = "Option<serde_json::Value>")]
     _meta: Option<Value>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl YamlContext {
+    fn schema_aliases(schema: &mut schemars::Schema) {
+        schema_alias(schema, "buildable", "is_builder");
+    }
+
+    /// Returns this context with `defaults` applied.
+    fn with_defaults(&self, defaults: &YamlContextDefaults) -> YamlContext {
+        let merged = YamlContextDefaults {
+            env: self.env.clone(),
+            selects: self.selects.clone(),
+            disables: self.disables.clone(),
+            requires: self.requires.clone(),
+            rules: self.rules.clone(),
+            var_options: self.var_options.clone(),
+            archive: self.archive,
+            tasks: self.tasks.clone(),
+        }
+        .merged_over(defaults);
+
+        YamlContext {
+            env: merged.env,
+            selects: merged.selects,
+            disables: merged.disables,
+            requires: merged.requires,
+            rules: merged.rules,
+            var_options: merged.var_options,
+            archive: merged.archive,
+            tasks: merged.tasks,
+            ..self.clone()
+        }
+    }
+
+    /// Returns one builder per combination of this builder's variant values.
+    ///
+    /// The builders are children of this one, named after it and the values
+    /// (`<name>:<value>:...`), with the values' `env` and `selects`, and
+    /// `${variant::<dimension>}` set to the value.
+    fn variant_builders(&self) -> Vec<YamlContext> {
+        let Some(dimensions) = self.variants.as_ref().filter(|d| !d.is_empty()) else {
+            return Vec::new();
+        };
+
+        dimensions
+            .iter()
+            .map(|dimension| dimension.values.iter().map(move |value| (dimension, value)))
+            .multi_cartesian_product()
+            .map(|combination| {
+                let mut name = self.name.clone();
+                let mut env = YamlEnv::new();
+                let mut selects = Vec::new();
+                for (dimension, value) in combination {
+                    name.push(':');
+                    name.push_str(&value.name);
+                    env.insert(
+                        format!("variant::{}", dimension.name),
+                        YamlEnvValue::Single(value.name.clone()),
+                    );
+                    if let Some(value_env) = &value.env {
+                        merge_yaml_env(&mut env, value_env);
+                    }
+                    selects.extend(value.selects.iter().flatten().cloned());
+                }
+                YamlContext {
+                    name,
+                    parent: Some(self.name.clone()),
+                    env: Some(env),
+                    selects: (!selects.is_empty()).then_some(selects),
+                    is_builder: true,
+                    _is_variant: true,
+                    ..YamlContext::default()
+                }
+            })
+            .collect()
+    }
+}
+
+/// A dimension of builder variants, e.g., the build type
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+#[schemars(rename = "VariantDimension")]
+struct YamlVariantDimension {
+    /// Name of this dimension. Builds get the chosen value as `${variant::<name>}`.
+    name: String,
+    /// The values of this dimension.
+    values: Vec<YamlVariant>,
+}
+
+/// A value of a builder variant dimension, e.g., `release`
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+#[schemars(rename = "Variant")]
+struct YamlVariant {
+    /// Name of this value, used in the names of the variant builders.
+    name: String,
+    /// Variables of builders with this value, merged over the builder's.
+    env: Option<YamlEnv>,
+    /// Modules that are selected for builders with this value.
+    selects: Option<Vec<String>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, JsonSchema)]
 #[serde(untagged)]
 enum StringOrVecString {
     Single(String),
     List(Vec<String>),
 }
 
-#[derive(Default, Debug, Serialize, Deserialize)]
+/// A module or app
+#[derive(Default, Debug, Serialize, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
+#[schemars(rename = "Module", transform = YamlModule::schema_aliases)]
 struct YamlModule {
-    #[serde(default = "default_none", deserialize_with = "check_module_name")]
+    /// Name of this module. Defaults to the name of the directory of the file.
+    #[serde(
+        default = "default_none::<String>",
+        deserialize_with = "check_module_name"
+    )]
     name: Option<String>,
+    /// The context(s) this module belongs to. Defaults to `default`.
     context: Option<StringOrVecString>,
+    /// Condition that needs to be `true` for this module to be defined, e.g., `${host::os} == "linux"`.
+    #[serde(rename = "if")]
+    condition: Option<String>,
+    /// Help text for this module.
     help: Option<String>,
+    /// Modules this module depends on (selects and uses). `?name` makes a dependency optional, `{ other: [name] }` makes it conditional on `other`, `{ if: <condition>, then: [name] }` on a condition.
     depends: Option<Vec<StringOrMapVecString>>,
+    /// Modules this module pulls into the build. `?name` makes a dependency optional, `{ other: [name] }` makes it conditional on `other`, `{ if: <condition>, then: [name] }` on a condition.
     selects: Option<Vec<StringOrMapVecString>>,
+    /// Modules whose exported variables this module imports, if they are part of the build.
     uses: Option<Vec<String>>,
+    /// Features that this module provides.
     provides: Option<Vec<String>>,
+    /// Features that this module provides, but needs to be the only provider of.
     provides_unique: Option<Vec<String>>,
+    /// Modules that cannot be part of a build together with this module.
     #[serde(alias = "disables")]
     conflicts: Option<Vec<String>>,
+    /// Modules that must be present in the build for this module to be valid.
     requires: Option<Vec<String>>,
+    /// Whether `${notify}` contains all modules of the build (instead of only the used ones). Defaults to `false`.
     #[serde(default = "default_as_false")]
     notify_all: bool,
-    sources: Option<Vec<StringOrMapVecString>>,
+    /// Source files used to build this module, or glob patterns (`src/**/*.c`, `!exclude.c`). `{ other: [file] }` only adds a file if `other` is part of the build, `{ if: <condition>, then: [file] }` if the condition is `true`. `{ file: <file>, env: ..., rule: ... }` sets variables or the rule for one file.
+    sources: Option<Vec<YamlSourceEntry>>,
+    /// Name of the rule to build all sources with, instead of the ones matching their extensions.
+    rule: Option<String>,
+    /// Build rules of this module, overriding the builder's.
+    rules: Option<Vec<YamlRule>>,
+    /// Whether modules using this one can use its rules. Defaults to `false`.
+    #[serde(default = "default_as_false")]
+    export_rules: bool,
+    /// Named tasks.
     tasks: Option<HashMap<String, YamlTask>>,
+    /// Custom build step (e.g., for code generation), instead of the rules.
     build: Option<CustomBuild>,
+    /// Whether to link this module's objects as static library (using the `AR` rule). Defaults to the context's `archive`, or `false`.
+    archive: Option<bool>,
+    /// Whether to link all of this module's archive, not only the needed objects. Implies `archive`. Defaults to `false`.
+    whole_archive: Option<bool>,
+    /// Prebuilt libraries or objects (relative to `srcdir`) that are linked into apps using this module.
+    libs: Option<Vec<String>>,
+    /// Files (relative to `srcdir`) that apps using this module are relinked on changes of, e.g., linker scripts.
+    link_deps: Option<Vec<String>>,
+    /// Variables of this module, in the `local`, `export` and `global` scopes.
     env: Option<YamlModuleEnv>,
+    /// Contexts (and their children) this module or app cannot be built for.
     blocklist: Option<Vec<String>>,
+    /// Contexts (and their children) this module or app can only be built for.
     allowlist: Option<Vec<String>>,
+    /// Source to download the module's files from.
     download: Option<Download>,
+    /// Base path for the module's source files.
+    #[schemars(with = "Option<String>")]
     srcdir: Option<Utf8PathBuf>,
+    /// Whether modules that depend on or use this module wait for its build outputs. Defaults to `false`.
     #[serde(default = "default_as_false")]
     is_build_dep: bool,
+    /// Whether all modules of the build wait for this module's build outputs. Defaults to `false`.
     #[serde(default = "default_as_false")]
     is_global_build_dep: bool,
+    /// Turns this module into a template, defining one module per instance instead.
+    instances: Option<Vec<YamlInstance>>,
     #[serde(skip)]
     _is_binary: bool,
+    /// Ignored by laze, can contain arbitrary data.
     #[serde(rename = "meta")]
+    #[schemars(with = "Option<serde_json::Value>")]
     _meta: Option<Value>,
 }
 
 impl YamlModule {
+    fn schema_aliases(schema: &mut schemars::Schema) {
+        schema_alias(schema, "disables", "conflicts");
+    }
+
     fn default_binary() -> YamlModule {
         YamlModule {
             _is_binary: true,
@@ -192,6 +500,22 @@ impl YamlModule {
         }
     }
 
+    /// Returns whether this module's `if:` condition holds (or it has none).
+    fn condition_holds(&self, conditions: &Conditions) -> Result<bool> {
+        match &self.condition {
+            Some(condition) => conditions.eval(condition),
+            None => Ok(true),
+        }
+    }
+
+    fn get_instances(&self) -> Vec<Option<&YamlInstance>> {
+        if let Some(instances) = &self.instances {
+            instances.iter().map(Some).collect_vec()
+        } else {
+            vec![None]
+        }
+    }
+
     fn get_contexts(&self) -> Vec<Option<&String>> {
         if let Some(contexts) = &self.context {
             match contexts {
@@ -204,44 +528,125 @@ impl YamlModule {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Variables of a module
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
+#[schemars(rename = "ModuleEnv")]
 struct YamlModuleEnv {
-    local: Option<Env>,
-    export: Option<Env>,
-    global: Option<Env>,
+    /// Variables only used when building this module.
+    local: Option<YamlEnv>,
+    /// Variables passed to modules using this one.
+    export: Option<YamlEnv>,
+    /// Variables added to the whole build.
+    global: Option<YamlEnv>,
+}
+
+/// An entry of a module's `sources`
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+#[serde(untagged)]
+#[schemars(rename = "SourceEntry")]
+enum YamlSourceEntry {
+    String(String),
+    If(Conditional<YamlSourceEntry>),
+    File(YamlSource),
+    Map(HashMap<String, Vec<String>>),
 }
 
-#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Clone)]
+/// A source file with its own variables or rule
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
 #[serde(deny_unknown_fields)]
+#[schemars(rename = "Source")]
+struct YamlSource {
+    /// Source file (or glob pattern), relative to the module's srcdir.
+    file: String,
+    /// Variables used only when building this file, merged over the module's.
+    env: Option<YamlEnv>,
+    /// Name of the rule to build this file with, instead of the one matching its extension.
+    rule: Option<String>,
+}
+
+/// An instance of a module template
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+#[schemars(rename = "Instance")]
+struct YamlInstance {
+    /// Name of the module defined by this instance. Available as `${instance}` in the template.
+    name: String,
+    /// Variables of this instance, merged over the template's.
+    env: Option<YamlModuleEnv>,
+}
+
+/// Variables, converted to [`Env`] after evaluating conditions
+type YamlEnv = HashMap<String, YamlEnvValue>;
+
+/// A variable's value. List entries can be conditional (`{ if: <condition>, then: [value] }`).
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+#[serde(untagged, expecting = "expected single value or array of values")]
+#[schemars(rename = "EnvValue")]
+enum YamlEnvValue {
+    Single(String),
+    List(Vec<StringOrConditional>),
+}
+
+/// A build rule
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Clone, JsonSchema)]
+#[serde(deny_unknown_fields)]
+#[schemars(rename = "Rule", transform = YamlRule::schema_aliases)]
 pub struct YamlRule {
+    /// Name of this rule (used as Ninja rule name).
     pub name: String,
+    /// Command to run when the output needs to be rebuilt.
     pub cmd: String,
 
+    /// Help text for this rule.
     pub help: Option<String>,
 
+    /// Extension(s) of the input files of this rule, or file name patterns (e.g., `*.pb.c`).
     #[serde(rename = "in")]
-    pub in_: Option<String>,
+    in_: Option<StringOrVecString>,
+    /// Extension of the output files of this rule.
     pub out: Option<String>,
+    /// Context that defines this rule. Set by laze, a value given here is ignored.
     pub context: Option<String>,
+    /// Currently unused.
     pub options: Option<HashMap<String, String>>,
+    /// Dependency file written by the compiler (usually containing `$out`), for header dependency tracking.
     pub gcc_deps: Option<String>,
+    /// File containing additional command line arguments.
     pub rspfile: Option<String>,
+    /// Contents of `rspfile`.
     pub rspfile_content: Option<String>,
+    /// Ninja pool to limit this rule's concurrency.
     pub pool: Option<String>,
+    /// Human readable description of this rule.
     pub description: Option<String>,
+    /// Variables to export to the rule's build environment.
     pub export: Option<Vec<StringOrMapString>>,
+    /// Makes this rule create the named artifact (e.g., `hex`) from each linked app, with the `out` extension.
+    pub artifact: Option<String>,
+    /// Artifact to create this rule's artifact from (e.g., `bin`), instead of the linked app.
+    pub artifact_from: Option<String>,
 
+    /// Whether this rule always runs. Defaults to `false`.
     #[serde(default = "default_as_false")]
     pub always: bool,
 
+    /// Whether outputs can be shared between builds. Defaults to `true`.
     #[serde(default = "default_as_true", alias = "sharable")]
     pub shareable: bool,
 
+    /// Ignored by laze, can contain arbitrary data.
     #[serde(rename = "meta")]
+    #[schemars(with = "Option<serde_json::Value>")]
     _meta: Option<Value>,
 }
 
+impl YamlRule {
+    fn schema_aliases(schema: &mut schemars::Schema) {
+        schema_alias(schema, "sharable", "shareable");
+    }
+}
+
 impl From<YamlRule> for Rule {
     //TODO: use deserialize_with as only the export field needs special handling
     fn from(yaml_rule: YamlRule) -> Self {
@@ -252,7 +657,11 @@ impl From<YamlRule> for Rule {
 
             name: yaml_rule.name,
             help: yaml_rule.help,
-            in_: yaml_rule.in_,
+            in_: match yaml_rule.in_ {
+                Some(StringOrVecString::Single(in_)) => vec![in_],
+                Some(StringOrVecString::List(in_)) => in_,
+                None => Vec::new(),
+            },
             out: yaml_rule.out,
             options: yaml_rule.options,
             gcc_deps: yaml_rule.gcc_deps,
@@ -264,24 +673,39 @@ impl From<YamlRule> for Rule {
             export: yaml_rule
                 .export
                 .map(|s| s.iter().map(|s| s.clone().into()).collect_vec()),
+            artifact: yaml_rule.artifact,
+            artifact_from: yaml_rule.artifact_from,
+            location: None,
         }
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Clone)]
+/// A task
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Clone, JsonSchema)]
 #[serde(deny_unknown_fields)]
+#[schemars(rename = "Task")]
 pub struct YamlTask {
+    /// Shell commands to execute sequentially.
     pub cmd: Vec<String>,
+    /// Help text for this task.
     pub help: Option<String>,
+    /// Variables that must be set for this task to be available.
     pub required_vars: Option<Vec<String>>,
+    /// Modules that must be part of the build for this task to be available.
     pub required_modules: Option<Vec<String>>,
+    /// Variables to export to the task's shell environment.
     pub export: Option<Vec<StringOrMapString>>,
+    /// Whether the app is built before executing this task. Defaults to `true`.
     #[serde(default = "default_as_true")]
     pub build: bool,
+    /// Whether Ctrl+C is ignored while this task runs. Defaults to `false`.
     #[serde(default = "default_as_false")]
     pub ignore_ctrl_c: bool,
+    /// Working directory of the task's commands.
     pub workdir: Option<String>,
+    /// Ignored by laze, can contain arbitrary data.
     #[serde(rename = "meta")]
+    #[schemars(with = "Option<serde_json::Value>")]
     _meta: Option<Value>,
 }
 
@@ -298,6 +722,7 @@ impl From<YamlTask> for Task {
             build: yaml_task.build,
             ignore_ctrl_c: yaml_task.ignore_ctrl_c,
             workdir: yaml_task.workdir,
+            location: None,
         }
     }
 }
@@ -341,9 +766,20 @@ fn load_all(file_include: &FileInclude, index_start: usize) -> Result<Vec<YamlFi
     let filename = &file_include.filename;
     let file = read_to_string(filename).with_context(|| format!("{:?}", filename))?;
 
+    let documents = location::parse(&file);
+
     let mut result = Vec::new();
     for (n, doc) in serde_yaml::Deserializer::from_str(&file).enumerate() {
-        let mut parsed = YamlFile::deserialize(doc).with_context(|| filename.clone())?;
+        let mut parsed = YamlFile::deserialize(doc).map_err(|e| {
+            let location = e
+                .location()
+                .map(|location| Location::at(filename, location.line(), location.column()));
+            location::locate(Error::from(e).context(filename.clone()), location.as_ref())
+        })?;
+        parsed.location = Some(match documents.get(n) {
+            Some(document) => Location::new(filename, document.clone()),
+            None => Location::file(filename),
+        });
         parsed.filename = Some(filename.clone());
         parsed.doc_idx = Some(index_start + n);
         parsed.included_by = file_include.included_by_doc_idx;
@@ -354,6 +790,17 @@ fn load_all(file_include: &FileInclude, index_start: usize) -> Result<Vec<YamlFi
     Ok(result)
 }
 
+impl YamlFile {
+    /// Returns the location of entry `index` of list `key`, or of this file
+    /// if that is unknown.
+    fn location_of(&self, key: &str, index: usize) -> Location {
+        self.location
+            .as_ref()
+            .and_then(|location| location.key(key)?.index(index))
+            .unwrap_or_else(|| Location::file(self.filename.as_ref().unwrap()))
+    }
+}
+
 #[derive(Hash, Debug, PartialEq, Eq, Clone)]
 struct ImportRoot(Utf8PathBuf);
 impl ImportRoot {
@@ -395,6 +842,42 @@ impl FileInclude {
     }
 }
 
+/// Adds `alias` as another name for property `name` (schemars ignores
+/// `#[serde(alias)]`).
+fn schema_alias(schema: &mut schemars::Schema, alias: &str, name: &str) {
+    if let Some(properties) = schema
+        .get_mut("properties")
+        .and_then(|properties| properties.as_object_mut())
+    {
+        if let Some(property) = properties.get(name).cloned() {
+            properties.insert(alias.into(), property);
+        }
+    }
+}
+
+/// Returns the JSON schema of laze files.
+pub fn schema() -> schemars::Schema {
+    schemars::schema_for!(YamlFile)
+}
+
+/// Returns the early env (`relpath` and `root`) of an entry defined in
+/// `filename`.
+fn file_env_early(filename: &Utf8Path, import_root: &Option<ImportRoot>) -> Env {
+    let relpath = match filename.parent().unwrap().as_str() {
+        "" => ".",
+        relpath => relpath,
+    };
+    let root = match import_root {
+        Some(import_root) => import_root.path().as_str(),
+        None => ".",
+    };
+
+    let mut env = Env::new();
+    env.insert("relpath".into(), EnvKey::Single(relpath.into()));
+    env.insert("root".into(), EnvKey::Single(root.into()));
+    env
+}
+
 pub fn load(
     filename: &Utf8Path,
     build_dir: &Utf8Path,
@@ -415,6 +898,12 @@ pub fn load(
     let mut local_file = main_file.clone();
     local_file.set_file_name("laze-local.yml");
 
+    // git imports are pinned to the commits in `laze.lock`
+    let mut lock = Lockfile::load(&main_file)?;
+    let mut missing_offline = Vec::new();
+    // patches applied to imports, watched like laze files
+    let mut import_patches = Vec::new();
+
     filenames.insert(FileInclude::new(main_file, None, None));
 
     if local_file.is_file() {
@@ -449,19 +938,37 @@ pub fn load(
                 }
             }
             if let Some(imports) = &new.imports {
+                let relpath = filename.parent().unwrap();
                 for import in imports {
+                    if let ImportEntry::Download(download) = import {
+                        import_patches.extend(download.patch_paths(relpath));
+                    }
                     // TODO: `import.handle()` does the actual git checkout (or whatever
                     // import action), so probably better handling of any errors is
                     // in order.
-                    filenames.insert(FileInclude::new_import(
-                        import.handle(build_dir)?,
-                        new.doc_idx,
-                    ));
+                    let import_file = match import.handle(build_dir, relpath, &mut lock) {
+                        Ok(import_file) => import_file,
+                        // in offline mode, collect all missing imports before failing
+                        Err(e) => match e.downcast::<offline::NotAvailable>() {
+                            Ok(missing) => {
+                                missing_offline.push(missing.0);
+                                continue;
+                            }
+                            Err(e) => return Err(e),
+                        },
+                    };
+                    filenames.insert(FileInclude::new_import(import_file, new.doc_idx));
                 }
             }
             if let Some(includes) = &new.includes {
                 let relpath = filename.parent().unwrap().to_path_buf();
-                for filename in includes {
+                let location = new
+                    .location
+                    .as_ref()
+                    .and_then(|location| location.key("includes"));
+                let env_early = file_env_early(&filename, &new.import_root);
+                let conditions = Conditions::new(&env_early, location.as_ref())?;
+                for filename in conditions.strings(includes)? {
                     let filepath = Utf8Path::new(&relpath).join(filename);
                     filenames.insert(FileInclude::new(
                         filepath,
@@ -473,12 +980,17 @@ pub fn load(
         }
     }
 
+    if !missing_offline.is_empty() {
+        return Err(offline::missing_error(&missing_offline));
+    }
+
     fn convert_context(
         context: &YamlContext,
         contexts: &mut ContextBag,
         is_builder: bool,
         filename: &Utf8PathBuf,
         import_root: &Option<ImportRoot>,
+        location: Location,
     ) -> Result<Module, Error> {
         let context_name = &context.name;
         let context_parent = match &context.parent {
@@ -497,27 +1009,43 @@ pub fn load(
         //     context_name,
         //     context_parent,
         // );
+        let mut new_context = Context::new(
+            context_name.clone(),
+            if is_default {
+                None
+            } else {
+                Some(context_parent.clone())
+            },
+        );
+        new_context.location = Some(location.clone());
+
         let context_ = contexts
-            .add_context_or_builder(
-                Context::new(
-                    context_name.clone(),
-                    if is_default {
-                        None
-                    } else {
-                        Some(context_parent.clone())
-                    },
-                ),
-                is_builder,
-            )
-            .with_context(|| format!("{:?}: adding context \"{}\"", &filename, &context_name))?;
+            .add_context_or_builder(new_context, is_builder)
+            .map_err(ContextBagError::located)
+            .with_context(|| format!("adding context \"{}\"", &context_name))?;
 
         context_.help.clone_from(&context.help);
-        context_.env.clone_from(&context.env);
+
+        // populate "early env"
+        context_.env_early = file_env_early(filename, import_root);
+
+        let env_early = context_.env_early.clone();
+        let conditions = Conditions::new(&env_early, Some(&location))?;
+        context_.env = context
+            .env
+            .as_ref()
+            .map(|env| conditions.env(env))
+            .transpose()?;
+        if let Some(env) = &mut context_.env {
+            let kind = if is_builder { "builder" } else { "context" };
+            env.set_origin(&format!("{kind} \"{context_name}\" ({filename})"));
+        }
         if let Some(rules) = &context.rules {
             context_.rules = Some(IndexMap::new());
-            for rule in rules {
+            for (i, rule) in rules.iter().enumerate() {
                 let mut rule: Rule = rule.clone().into();
                 rule.context = Some(context_name.clone());
+                rule.location = location.key("rules").and_then(|rules| rules.index(i));
                 context_
                     .rules
                     .as_mut()
@@ -526,36 +1054,17 @@ pub fn load(
             }
         }
         context_.var_options.clone_from(&context.var_options);
-        // populate "early env"
-        let relpath = {
-            let relpath = filename.parent().unwrap().as_str();
-            if relpath.is_empty() {
-                ".".to_string()
-            } else {
-                relpath.to_string()
-            }
-        };
-
-        context_
-            .env_early
-            .insert("relpath".into(), EnvKey::Single(relpath));
-
-        context_.env_early.insert(
-            "root".into(),
-            EnvKey::Single(match import_root {
-                Some(import_root) => import_root.path().to_string(),
-                None => ".".into(),
-            }),
-        );
+        context_.archive = context.archive;
+        context_.is_variant = context._is_variant;
 
         if let Some(tasks) = &context.tasks {
             context_.tasks = Some(
-                convert_tasks(tasks, &context_.env_early)
-                    .with_context(|| format!("{:?} context \"{}\"", &filename, context.name))?,
+                convert_tasks(tasks, &context_.env_early, &location)
+                    .with_context(|| format!("context \"{}\"", context.name))?,
             )
         }
 
-        context_.apply_early_env()?;
+        context_.apply_early_env().located(Some(&location))?;
 
         context_.defined_in = Some(filename.clone());
 
@@ -580,6 +1089,7 @@ pub fn load(
             filename,
             import_root,
             None,
+            location,
         );
 
         // collect context level "select:"
@@ -632,6 +1142,7 @@ pub fn load(
         filename: &Utf8Path,
         import_root: &Option<ImportRoot>,
         defaults: Option<&Module>,
+        location: Location,
     ) -> Module {
         let relpath = filename.parent().unwrap();
 
@@ -656,6 +1167,7 @@ pub fn load(
 
         module.is_binary = is_binary;
         module.defined_in = Some(filename.to_path_buf());
+        module.location = Some(location);
         module.relpath = Some(if relpath.eq("") {
             Utf8PathBuf::from(".")
         } else {
@@ -665,26 +1177,108 @@ pub fn load(
         module
     }
 
+    fn merge_module_env(
+        m: &mut Module,
+        env: &YamlModuleEnv,
+        origin: &str,
+        conditions: &Conditions,
+    ) -> Result<(), Error> {
+        if let Some(local) = &env.local {
+            m.env_local
+                .merge(&conditions.env(local)?.with_origin(origin));
+        }
+        if let Some(export) = &env.export {
+            m.env_export
+                .merge(&conditions.env(export)?.with_origin(origin));
+        }
+        if let Some(global) = &env.global {
+            m.env_global
+                .merge(&conditions.env(global)?.with_origin(origin));
+        }
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_arguments)]
     fn convert_module(
         module: &YamlModule,
         context: Option<&String>,
+        instance: Option<&YamlInstance>,
         is_binary: bool,
         filename: &Utf8Path,
         import_root: &Option<ImportRoot>,
         defaults: Option<&Module>,
         build_dir: &Utf8Path,
-    ) -> Result<Module, Error> {
+        location: Location,
+    ) -> Result<Option<Module>, Error> {
+        let name = match instance {
+            Some(instance) => Some(instance.name.clone()),
+            None => module.name.clone(),
+        };
         let mut m = init_module(
-            &module.name,
+            &name,
             context,
             is_binary,
             filename,
             import_root,
             defaults,
+            location.clone(),
         );
 
         m.help.clone_from(&module.help);
 
+        let relpath = m.relpath.as_ref().unwrap().clone();
+
+        m.download.clone_from(&module.download);
+        let srcdir = if let Some(download) = &m.download {
+            let srcdir = download.srcdir(build_dir, &m);
+            let tagfile = download.tagfile(&srcdir);
+
+            m.add_build_dep_file(&tagfile);
+
+            // if a module has downloaded files, always consider it to be a
+            // build dependency, as all dependees / users might include e.g.,
+            // downloaded headers.
+            m.is_build_dep = true;
+
+            srcdir
+        } else if relpath != "." {
+            relpath.clone()
+        } else {
+            "".into()
+        };
+
+        m.srcdir = module
+            .srcdir
+            .as_ref()
+            .map_or(Some(srcdir), |s| Some(Utf8PathBuf::from(s)));
+
+        // populate "early env"
+        m.env_early
+            .insert("relpath".into(), EnvKey::Single(relpath.to_string()));
+
+        m.env_early.insert(
+            "root".into(),
+            EnvKey::Single(match import_root {
+                Some(import_root) => import_root.path().to_string(),
+                None => ".".into(),
+            }),
+        );
+        m.env_early.insert(
+            "srcdir".into(),
+            EnvKey::Single(m.srcdir.as_ref().unwrap().as_path().to_string()),
+        );
+        if instance.is_some() {
+            m.env_early
+                .insert("instance".into(), EnvKey::Single(m.name.clone()));
+        }
+
+        // conditions can refer to the early env, e.g., `${srcdir}`
+        let env_early = m.env_early.clone();
+        let conditions = Conditions::new(&env_early, Some(&location))?;
+        if !module.condition_holds(&conditions)? {
+            return Ok(None);
+        }
+
         // convert module dependencies
         // "selects" means "module will be part of the build"
         // "uses" means "if module is part of the build, transitively import its exported env vars"
@@ -697,11 +1291,12 @@ pub fn load(
         //
         if let Some(selects) = &module.selects {
             // println!("selects:");
-            for dep_spec in selects {
+            for dep_spec in &conditions.entries(selects)? {
                 match dep_spec {
                     StringOrMapVecString::String(dep_name) => {
                         m.selects.push(dependency_from_string(dep_name));
                     }
+                    StringOrMapVecString::If(_) => unreachable!("conditions are resolved"),
                     StringOrMapVecString::Map(dep_map) => {
                         for (k, v) in dep_map {
                             for dep_name in v {
@@ -721,13 +1316,14 @@ pub fn load(
         }
         if let Some(depends) = &module.depends {
             // println!("depends:");
-            for dep_spec in depends {
+            for dep_spec in &conditions.entries(depends)? {
                 match dep_spec {
                     StringOrMapVecString::String(dep_name) => {
                         // println!("- {}", dep_name);
                         m.selects.push(dependency_from_string(dep_name));
                         m.imports.push(dependency_from_string(dep_name));
                     }
+                    StringOrMapVecString::If(_) => unreachable!("conditions are resolved"),
                     StringOrMapVecString::Map(dep_map) => {
                         for (k, v) in dep_map {
                             // println!("- {}:", k);
@@ -775,23 +1371,45 @@ pub fn load(
 
         // copy over environment
         if let Some(env) = &module.env {
-            if let Some(local) = &env.local {
-                m.env_local.merge(local);
-            }
-            if let Some(export) = &env.export {
-                m.env_export.merge(export);
-            }
-            if let Some(global) = &env.global {
-                m.env_global.merge(global);
-            }
+            let kind = if is_binary { "app" } else { "module" };
+            let origin = format!("{kind} \"{}\" ({filename})", m.name);
+            merge_module_env(&mut m, env, &origin, &conditions)?;
+        }
+
+        // an instance's env goes on top of its template's
+        if let Some(env) = instance.and_then(|instance| instance.env.as_ref()) {
+            let origin = format!("instance \"{}\" ({filename})", m.name);
+            merge_module_env(&mut m, env, &origin, &conditions)?;
         }
 
         if let Some(sources) = &module.sources {
+            let sources = conditions.entries(sources)?;
             let mut sources_optional = IndexMap::new();
-            for source in sources {
+            for source in &sources {
                 match source {
-                    StringOrMapVecString::String(source) => m.sources.push(source.clone()),
-                    StringOrMapVecString::Map(source) => {
+                    YamlSourceEntry::String(source) => m.sources.push(source.clone()),
+                    YamlSourceEntry::If(_) => unreachable!("conditions are resolved"),
+                    YamlSourceEntry::File(source) => {
+                        m.sources.push(source.file.clone());
+                        if source.env.is_some() || source.rule.is_some() {
+                            let origin = format!(
+                                "source \"{}\" of module \"{}\" ({filename})",
+                                source.file, m.name
+                            );
+                            let env = match &source.env {
+                                Some(env) => Some(conditions.env(env)?.with_origin(&origin)),
+                                None => None,
+                            };
+                            m.source_overrides.insert(
+                                source.file.clone(),
+                                SourceOverride {
+                                    env,
+                                    rule: source.rule.clone(),
+                                },
+                            );
+                        }
+                    }
+                    YamlSourceEntry::Map(source) => {
                         // collect optional sources into sources_optional
                         for (k, v) in source {
                             let list: &mut Vec<String> = sources_optional.entry(k).or_default();
@@ -835,28 +1453,33 @@ pub fn load(
             m.allowlist.clone_from(&module.allowlist);
         }
 
-        let relpath = m.relpath.as_ref().unwrap().clone();
-
-        m.download.clone_from(&module.download);
-        let srcdir = if let Some(download) = &m.download {
-            let srcdir = download.srcdir(build_dir, &m);
-            let tagfile = download.tagfile(&srcdir);
-
-            m.add_build_dep_file(&tagfile);
-
-            // if a module has downloaded files, always consider it to be a
-            // build dependency, as all dependees / users might include e.g.,
-            // downloaded headers.
-            m.is_build_dep = true;
-
-            srcdir
-        } else if relpath != "." {
-            relpath.clone()
-        } else {
-            "".into()
-        };
-
         m.build.clone_from(&module.build);
+        if module.rule.is_some() {
+            m.rule.clone_from(&module.rule);
+        }
+        if let Some(rules) = &module.rules {
+            let m_rules = m.rules.get_or_insert_with(IndexMap::new);
+            for (i, rule) in rules.iter().enumerate() {
+                let mut rule: Rule = rule.clone().into();
+                rule.location = location.key("rules").and_then(|rules| rules.index(i));
+                m_rules.insert(rule.name.clone(), rule);
+            }
+        }
+        if module.export_rules {
+            m.export_rules = true;
+        }
+        if module.archive.is_some() {
+            m.archive = module.archive;
+        }
+        if let Some(whole_archive) = module.whole_archive {
+            m.whole_archive = whole_archive;
+        }
+        if let Some(libs) = &module.libs {
+            m.libs.extend(libs.iter().cloned());
+        }
+        if let Some(link_deps) = &module.link_deps {
+            m.link_deps.extend(link_deps.iter().cloned());
+        }
         m.is_global_build_dep = module.is_global_build_dep;
 
         if m.download.is_none() {
@@ -865,34 +1488,29 @@ pub fn load(
             m.is_build_dep = module.is_build_dep;
         }
 
-        m.srcdir = module
-            .srcdir
-            .as_ref()
-            .map_or(Some(srcdir), |s| Some(Utf8PathBuf::from(s)));
-
-        // populate "early env"
-        m.env_early
-            .insert("relpath".into(), EnvKey::Single(relpath.to_string()));
-
-        m.env_early.insert(
-            "root".into(),
-            EnvKey::Single(match import_root {
-                Some(import_root) => import_root.path().to_string(),
-                None => ".".into(),
-            }),
-        );
-        m.env_early.insert(
-            "srcdir".into(),
-            EnvKey::Single(m.srcdir.as_ref().unwrap().as_path().to_string()),
-        );
-
         m.env_local.merge(&m.env_early);
-        m.apply_early_env()?;
+        m.apply_early_env().located(m.location.as_ref())?;
+        for env in m
+            .source_overrides
+            .values_mut()
+            .filter_map(|source_override| source_override.env.as_mut())
+        {
+            env.expand(&m.env_early).located(m.location.as_ref())?;
+        }
+
+        // expand the early env in the module's rules, so `${relpath}` etc.
+        // refer to this module even when its rules are used by another one
+        if let Some(rules) = &mut m.rules {
+            let env_early = m.env_early.flatten()?;
+            for rule in rules.values_mut() {
+                rule.cmd = nested_env::expand(&rule.cmd, &env_early, IfMissing::Ignore)?;
+            }
+        }
 
         // handle module tasks
         if let Some(tasks) = &module.tasks {
-            m.tasks = convert_tasks(tasks, &m.env_early)
-                .with_context(|| format!("{:?} module \"{}\"", &filename, m.name))?;
+            m.tasks = convert_tasks(tasks, &m.env_early, m.location.as_ref().unwrap())
+                .with_context(|| format!("module \"{}\"", m.name))?;
 
             // This makes the module provide_unique a marker module `::task::<task-name>`
             // for each task it defines, enabling the dependency resolver to sort
@@ -906,25 +1524,142 @@ pub fn load(
                 .insert("appdir".into(), EnvKey::Single(relpath.to_string()));
         }
 
-        Ok(m)
+        Ok(Some(m))
+    }
+
+    // determines the context or builder defaults for a given YamlFile, like
+    // `get_defaults()` does for modules.
+    // they are returned as layers, one per file setting defaults (by its index),
+    // starting with the outermost subdirectory.
+    fn get_context_defaults<'a>(
+        data: &'a YamlFile,
+        defaults_map: &HashMap<usize, Vec<(usize, &'a YamlContextDefaults)>>,
+        key: &str,
+    ) -> Vec<(usize, &'a YamlContextDefaults)> {
+        let mut layers = data
+            .included_by
+            .as_ref()
+            .and_then(|included_by| defaults_map.get(included_by))
+            .cloned()
+            .unwrap_or_default();
+        if let Some(defaults) = data
+            .defaults
+            .as_ref()
+            .and_then(|defaults| defaults.context(key))
+        {
+            layers.push((data.doc_idx.unwrap(), defaults));
+        }
+        layers
+    }
+
+    // determine the context and builder defaults of each file
+    let mut file_context_defaults = Vec::new();
+    let mut subdir_context_defaults_map = HashMap::new();
+    let mut subdir_builder_defaults_map = HashMap::new();
+    for data in &yaml_datas {
+        let context_defaults = get_context_defaults(data, &subdir_context_defaults_map, "context");
+        let builder_defaults = get_context_defaults(data, &subdir_builder_defaults_map, "builder");
+
+        if data.subdirs.is_some() {
+            subdir_context_defaults_map.insert(data.doc_idx.unwrap(), context_defaults.clone());
+            subdir_builder_defaults_map.insert(data.doc_idx.unwrap(), builder_defaults.clone());
+        }
+
+        file_context_defaults.push((context_defaults, builder_defaults));
     }
 
+    // map context name -> (parent, is builder, defaults layers)
+    let mut context_defaults_map = HashMap::new();
+    for (data, (context_defaults, builder_defaults)) in
+        yaml_datas.iter().zip(&file_context_defaults)
+    {
+        for (list, is_builder) in [(&data.contexts, false), (&data.builders, true)] {
+            for context in list.iter().flatten() {
+                let is_builder = is_builder || context.is_builder;
+                let defaults = if is_builder {
+                    builder_defaults
+                } else {
+                    context_defaults
+                };
+                let parent = match context.name.as_str() {
+                    "default" => None,
+                    _ => Some(context.parent.as_deref().unwrap_or("default")),
+                };
+                context_defaults_map.insert(context.name.as_str(), (parent, is_builder, defaults));
+            }
+        }
+    }
+
+    // returns the defaults for a context, leaving out the layers that one of
+    // its parents of the same kind (context or builder) already got, as the
+    // context inherits those anyways
+    let context_defaults_for = |context: &YamlContext| -> Option<YamlContextDefaults> {
+        let (mut parent, is_builder, defaults) = context_defaults_map[context.name.as_str()];
+        let mut inherited = HashSet::new();
+        let mut seen = HashSet::new();
+        while let Some(name) = parent {
+            // parent cycles are reported later
+            if !seen.insert(name) {
+                break;
+            }
+            let Some((grandparent, parent_is_builder, parent_defaults)) =
+                context_defaults_map.get(name)
+            else {
+                break;
+            };
+            if *parent_is_builder == is_builder {
+                inherited.extend(parent_defaults.iter().map(|(idx, _)| *idx));
+            }
+            parent = *grandparent;
+        }
+
+        defaults
+            .iter()
+            .filter(|(idx, _)| !inherited.contains(idx))
+            .fold(None, |merged, (_, layer)| match merged {
+                None => Some((*layer).clone()),
+                Some(merged) => Some(layer.merged_over(&merged)),
+            })
+    };
+
     // collect and convert contexts
     // this needs to be done before collecting modules, as that requires
     // contexts to be finalized.
     let mut context_modules = Vec::new();
     for data in &yaml_datas {
-        for (list, is_builder) in [(&data.contexts, false), (&data.builders, true)].iter() {
+        for (list, key, is_builder) in [
+            (&data.contexts, "contexts", false),
+            (&data.builders, "builders", true),
+        ] {
             if let Some(context_list) = list {
-                for context in context_list {
+                for (i, context) in context_list.iter().enumerate() {
+                    let with_defaults = context_defaults_for(context)
+                        .map(|defaults| context.with_defaults(&defaults));
+                    let context = with_defaults.as_ref().unwrap_or(context);
+
+                    // a builder with variants only builds those
+                    let variants = context.variant_builders();
                     let module = convert_context(
                         context,
                         &mut contexts,
-                        *is_builder | context.is_builder,
+                        (is_builder | context.is_builder) && variants.is_empty(),
                         data.filename.as_ref().unwrap(),
                         &data.import_root,
+                        data.location_of(key, i),
                     )?;
                     context_modules.push(module);
+
+                    for variant in &variants {
+                        let module = convert_context(
+                            variant,
+                            &mut contexts,
+                            true,
+                            data.filename.as_ref().unwrap(),
+                            &data.import_root,
+                            data.location_of(key, i),
+                        )?;
+                        context_modules.push(module);
+                    }
                 }
             }
         }
@@ -932,11 +1667,13 @@ pub fn load(
 
     // after this, there's a default context, context relationships and envs have been set up.
     // modules can now be processed.
-    contexts.finalize()?;
+    contexts.finalize().map_err(ContextBagError::located)?;
 
     // add the associated modules to their respective contexts
     for module in context_modules.drain(..) {
-        contexts.add_module(module)?;
+        contexts
+            .add_module(module)
+            .map_err(ContextBagError::located)?;
     }
 
     // for context in &contexts.contexts {
@@ -954,7 +1691,7 @@ pub fn load(
         key: &str,
         is_binary: bool,
         build_dir: &Utf8Path,
-    ) -> Option<Module> {
+    ) -> Result<Option<Module>> {
         // this function determines the module or app defaults for a given YamlFile
 
         // determine inherited "defaults: module: ..."
@@ -966,7 +1703,7 @@ pub fn load(
 
         // determine "defaults: module: ..." from yaml document
         let mut module_defaults = if let Some(defaults) = &data.defaults {
-            if let Some(module_defaults) = defaults.get(key) {
+            if let Some(module_defaults) = defaults.module(key) {
                 let context = &module_defaults
                     .context
                     .as_ref()
@@ -977,18 +1714,37 @@ pub fn load(
                         StringOrVecString::Single(context) => context,
                     });
 
-                Some(
-                    convert_module(
-                        module_defaults,
-                        *context,
-                        is_binary,
-                        data.filename.as_ref().unwrap(),
-                        &data.import_root,
-                        subdir_defaults,
-                        build_dir,
-                    )
-                    .unwrap(),
-                )
+                let filename = data.filename.as_ref().unwrap();
+                let location = data
+                    .location
+                    .as_ref()
+                    .and_then(|location| location.key("defaults")?.key(key))
+                    .unwrap_or_else(|| Location::file(filename));
+
+                if module_defaults.instances.is_some() {
+                    return Err(anyhow!("{key} defaults cannot have instances"))
+                        .located(location.key("instances").as_ref());
+                }
+
+                let module_defaults = convert_module(
+                    module_defaults,
+                    *context,
+                    None,
+                    is_binary,
+                    filename,
+                    &data.import_root,
+                    subdir_defaults,
+                    build_dir,
+                    location,
+                )?;
+
+                module_defaults.map(|mut module_defaults| {
+                    let origin = format!("{key} defaults ({})", data.filename.as_ref().unwrap());
+                    module_defaults.env_local.set_origin(&origin);
+                    module_defaults.env_export.set_origin(&origin);
+                    module_defaults.env_global.set_origin(&origin);
+                    module_defaults
+                })
             } else {
                 None
             }
@@ -1000,7 +1756,7 @@ pub fn load(
                 module_defaults = Some(subdir_defaults.clone());
             }
         }
-        module_defaults
+        Ok(module_defaults)
     }
 
     for data in &yaml_datas {
@@ -1010,8 +1766,8 @@ pub fn load(
             "module",
             false,
             build_dir,
-        );
-        let app_defaults = get_defaults(data, &subdir_app_defaults_map, "app", true, build_dir);
+        )?;
+        let app_defaults = get_defaults(data, &subdir_app_defaults_map, "app", true, build_dir)?;
 
         if data.subdirs.is_some() {
             if let Some(module_defaults) = &module_defaults {
@@ -1022,46 +1778,98 @@ pub fn load(
             }
         }
 
-        for (list, is_binary) in [(&data.modules, false), (&data.apps, true)].iter() {
+        for (list, key, is_binary) in [
+            (&data.modules, "modules", false),
+            (&data.apps, "apps", true),
+        ] {
             if let Some(module_list) = list {
                 if let Some(module_list) = module_list {
-                    for module in module_list {
+                    for (i, module) in module_list.iter().enumerate() {
+                        let filename = data.filename.as_ref().unwrap();
+                        let location = data.location_of(key, i);
                         for context in module.get_contexts() {
-                            contexts.add_module(convert_module(
-                                module,
-                                context,
-                                *is_binary,
-                                data.filename.as_ref().unwrap(),
-                                &data.import_root,
-                                if *is_binary {
-                                    app_defaults.as_ref()
-                                } else {
-                                    module_defaults.as_ref()
-                                },
-                                build_dir,
-                            )?)?;
+                            for instance in module.get_instances() {
+                                let module = convert_module(
+                                    module,
+                                    context,
+                                    instance,
+                                    is_binary,
+                                    filename,
+                                    &data.import_root,
+                                    if is_binary {
+                                        app_defaults.as_ref()
+                                    } else {
+                                        module_defaults.as_ref()
+                                    },
+                                    build_dir,
+                                    location.clone(),
+                                )?;
+                                // `None` if the module's condition is false
+                                if let Some(module) = module {
+                                    contexts
+                                        .add_module(module)
+                                        .map_err(ContextBagError::located)?;
+                                }
+                            }
                         }
                     }
-                } else if *is_binary {
+                } else if is_binary {
                     // if an app list is empty, add a default entry.
                     // this allows a convenient file only containing "app:"
                     let module = YamlModule::default_binary();
+                    let filename = data.filename.as_ref().unwrap();
+                    let location = data
+                        .location
+                        .as_ref()
+                        .and_then(|location| location.key(key))
+                        .unwrap_or_else(|| Location::file(filename));
                     for context in module.get_contexts() {
-                        contexts.add_module(convert_module(
+                        let module = convert_module(
                             &module,
                             context,
-                            *is_binary,
-                            data.filename.as_ref().unwrap(),
+                            None,
+                            is_binary,
+                            filename,
                             &data.import_root,
                             app_defaults.as_ref(),
                             build_dir,
-                        )?)?;
+                            location.clone(),
+                        )?
+                        .expect("default app has no condition");
+                        contexts
+                            .add_module(module)
+                            .map_err(ContextBagError::located)?;
                     }
                 }
             }
         }
     }
 
+    // expand glob patterns in sources. this needs the final srcdir, so it
+    // is done after all defaults have been applied.
+    let mut watched_dirs = IndexSet::new();
+    for context in &mut contexts.contexts {
+        for module in context.modules.values_mut() {
+            globs::expand_module_sources(module, &mut watched_dirs)?;
+        }
+    }
+
+    // git downloads following a branch or tag only get resolved once a build
+    // uses them, but their entries in `laze.lock` are kept
+    for (_, module) in contexts.modules() {
+        if let Some(Download {
+            source: Source::Git(git),
+            ..
+        }) = &module.download
+        {
+            lock.declare(git);
+        }
+    }
+
+    // all imports have been handled, update (or check) `laze.lock`
+    let lock_file = lock.path().to_path_buf();
+    lock.store()?;
+
     contexts.merge_provides();
 
     let parsing_time = start.elapsed();
@@ -1071,7 +1879,11 @@ pub fn load(
     // TODO: make treestate support camino Utf8PathBuf
     let filenames = filenames
         .drain(..)
-        .map(|include| include.filename.into_std_path_buf())
+        .map(|include| include.filename)
+        .chain(watched_dirs)
+        .chain(import_patches)
+        .chain(lock_file.exists().then_some(lock_file))
+        .map(Utf8PathBuf::into_std_path_buf)
         .collect_vec();
 
     let treestate = FileTreeState::new(filenames.iter());
@@ -1088,14 +1900,18 @@ pub fn load(
 fn convert_tasks(
     tasks: &HashMap<String, YamlTask>,
     env: &Env,
+    location: &Location,
 ) -> Result<HashMap<String, Task>, Error> {
     let flattened_env = env.flatten()?;
     tasks
         .iter()
         .map(|(name, task)| {
-            let task = Task::from(task.clone());
+            let location = location.key("tasks").and_then(|tasks| tasks.key(name));
+            let mut task = Task::from(task.clone());
+            task.location.clone_from(&location);
             let task = task.with_env(&flattened_env);
             task.map(|task| (name.clone(), task))
+                .located(location.as_ref())
                 .with_context(|| format!("task \"{}\"", name.clone()))
         })
         .collect::<Result<_, _>>()
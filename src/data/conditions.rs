@@ -0,0 +1,113 @@
+//! Evaluation of `if:` conditions in laze files
+//!
+//! Conditions are evaluated while loading, against the early env of the entry
+//! they belong to (e.g., `relpath`, `root`, and `srcdir` for modules), plus
+//! `host::os` and `host::arch`. Entries whose condition is `false` are dropped.
+
+use std::borrow::Cow;
+
+use anyhow::{Context as _, Result};
+
+use super::{YamlEnv, YamlEnvValue, YamlSourceEntry};
+use crate::location::{Locate, Location};
+use crate::nested_env::{self, Env, EnvKey, EnvMap};
+use crate::utils::{Conditional, StringOrConditional, StringOrMapVecString};
+
+/// List entries that can be `{ if: <condition>, then: [...] }`
+pub(super) trait ConditionalEntry: Clone + Sized {
+    fn conditional(&self) -> Option<&Conditional<Self>>;
+}
+
+impl ConditionalEntry for StringOrMapVecString {
+    fn conditional(&self) -> Option<&Conditional<Self>> {
+        match self {
+            StringOrMapVecString::If(conditional) => Some(conditional),
+            _ => None,
+        }
+    }
+}
+
+impl ConditionalEntry for YamlSourceEntry {
+    fn conditional(&self) -> Option<&Conditional<Self>> {
+        match self {
+            YamlSourceEntry::If(conditional) => Some(conditional),
+            _ => None,
+        }
+    }
+}
+
+pub(super) struct Conditions<'a> {
+    env: EnvMap<'a>,
+    location: Option<&'a Location>,
+}
+
+impl<'a> Conditions<'a> {
+    /// Creates the condition environment of an entry with early env
+    /// `env_early`.
+    ///
+    /// Errors are located below `location`.
+    pub(super) fn new(env_early: &'a Env, location: Option<&'a Location>) -> Result<Self> {
+        let mut env = env_early.flatten().located(location)?;
+        env.insert("host::os", Cow::from(std::env::consts::OS));
+        env.insert("host::arch", Cow::from(std::env::consts::ARCH));
+
+        Ok(Conditions { env, location })
+    }
+
+    /// Evaluates `condition`.
+    pub(super) fn eval(&self, condition: &str) -> Result<bool> {
+        let location = self.location.map(|location| {
+            location
+                .search(condition)
+                .unwrap_or_else(|| location.clone())
+        });
+        nested_env::eval_condition(condition, &self.env)
+            .located(location.as_ref())
+            .with_context(|| format!("evaluating condition \"{condition}\""))
+    }
+
+    /// Returns `entries` with conditional entries resolved.
+    pub(super) fn entries<T: ConditionalEntry>(&self, entries: &[T]) -> Result<Vec<T>> {
+        let mut result = Vec::new();
+        for entry in entries {
+            match entry.conditional() {
+                Some(conditional) => {
+                    if self.eval(&conditional.condition)? {
+                        result.extend(self.entries(&conditional.then)?);
+                    }
+                }
+                None => result.push(entry.clone()),
+            }
+        }
+        Ok(result)
+    }
+
+    /// Returns `entries` with conditional entries resolved.
+    pub(super) fn strings(&self, entries: &[StringOrConditional]) -> Result<Vec<String>> {
+        let mut result = Vec::new();
+        for entry in entries {
+            match entry {
+                StringOrConditional::String(string) => result.push(string.clone()),
+                StringOrConditional::If(conditional) => {
+                    if self.eval(&conditional.condition)? {
+                        result.extend(conditional.then.iter().cloned());
+                    }
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    /// Converts `env`, resolving conditional list entries.
+    pub(super) fn env(&self, env: &YamlEnv) -> Result<Env> {
+        let mut result = Env::new();
+        for (key, value) in env {
+            let value = match value {
+                YamlEnvValue::Single(value) => EnvKey::Single(value.clone()),
+                YamlEnvValue::List(list) => EnvKey::List(self.strings(list)?.into()),
+            };
+            result.insert(key.clone(), value);
+        }
+        Ok(result)
+    }
+}
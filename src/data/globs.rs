@@ -0,0 +1,254 @@
+//! Expansion of glob patterns in module `sources:`
+
+use anyhow::{anyhow, Result};
+use camino::{Utf8Path, Utf8PathBuf};
+use glob::{MatchOptions, Pattern};
+use indexmap::{IndexMap, IndexSet};
+
+use crate::location::{self, Locate};
+use crate::model::SourceOverride;
+use crate::Module;
+
+const MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: true,
+};
+
+fn is_glob(source: &str) -> bool {
+    source.starts_with('!') || source.contains(['*', '?', '['])
+}
+
+/// Expands glob patterns in the sources of `module`, relative to its srcdir.
+///
+/// Patterns starting with `!` remove matching files from the list. Overrides
+/// of a pattern apply to all files it matches.
+/// All directories that were searched are added to `watched`, so adding or
+/// removing files there invalidates the build cache.
+pub(super) fn expand_module_sources(
+    module: &mut Module,
+    watched: &mut IndexSet<Utf8PathBuf>,
+) -> Result<()> {
+    let has_globs = module.sources.iter().any(|source| is_glob(source))
+        || module
+            .sources_optional
+            .iter()
+            .flat_map(|map| map.values().flatten())
+            .any(|source| is_glob(source));
+    if !has_globs {
+        return Ok(());
+    }
+
+    let module_location = module.location.clone();
+    let entry_location = |source: &str| {
+        module_location
+            .as_ref()
+            .map(|location| location.entry(&["sources"], source))
+    };
+
+    let find_glob = |sources: &[String]| sources.iter().find(|source| is_glob(source)).cloned();
+    let first_glob = find_glob(&module.sources).or_else(|| {
+        module
+            .sources_optional
+            .iter()
+            .flat_map(|map| map.values())
+            .find_map(|sources| find_glob(sources))
+    });
+
+    if let Some(pattern) = first_glob {
+        if module.download.is_some() {
+            return Err(anyhow!(
+                "module \"{}\": glob pattern \"{pattern}\" cannot be used in sources of a module with `download`",
+                module.name
+            ))
+            .located(entry_location(&pattern).as_ref());
+        }
+    }
+
+    let srcdir = module.srcdir.clone().unwrap_or_default();
+    if srcdir.as_str().contains('$') {
+        return Err(anyhow!(
+            "module \"{}\": glob patterns in sources need a srcdir without variables (got \"{srcdir}\")",
+            module.name
+        ))
+        .located(module.location.as_ref());
+    }
+
+    let overrides = &mut module.source_overrides;
+    module.sources = expand(&srcdir, &module.sources, overrides, watched)
+        .map_err(|(pattern, e)| location::locate(e, entry_location(&pattern).as_ref()))?;
+
+    if let Some(sources_optional) = &mut module.sources_optional {
+        for sources in sources_optional.values_mut() {
+            *sources = expand(&srcdir, sources, overrides, watched)
+                .map_err(|(pattern, e)| location::locate(e, entry_location(&pattern).as_ref()))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Expands `sources`, returning the offending pattern on error.
+fn expand(
+    srcdir: &Utf8Path,
+    sources: &[String],
+    overrides: &mut IndexMap<String, SourceOverride>,
+    watched: &mut IndexSet<Utf8PathBuf>,
+) -> Result<Vec<String>, (String, anyhow::Error)> {
+    let mut result = IndexSet::new();
+    let mut excludes = Vec::new();
+
+    for source in sources {
+        if !is_glob(source) {
+            result.insert(source.clone());
+            continue;
+        }
+
+        let invalid = |e| {
+            (
+                source.clone(),
+                anyhow!("invalid glob pattern \"{source}\": {e}"),
+            )
+        };
+        if let Some(exclude) = source.strip_prefix('!') {
+            excludes.push(Pattern::new(exclude).map_err(invalid)?);
+            continue;
+        }
+
+        let pattern = Pattern::new(source).map_err(invalid)?;
+        let mut matches = Vec::new();
+        find_matches(srcdir, source, &pattern, watched, &mut matches)
+            .map_err(|e| (source.clone(), e))?;
+        matches.sort();
+        if let Some(source_override) = overrides.shift_remove(source) {
+            for file in &matches {
+                overrides
+                    .entry(file.clone())
+                    .or_insert_with(|| source_override.clone());
+            }
+        }
+        result.extend(matches);
+    }
+
+    Ok(result
+        .into_iter()
+        .filter(|source| {
+            !excludes
+                .iter()
+                .any(|exclude| exclude.matches_with(source, MATCH_OPTIONS))
+        })
+        .collect())
+}
+
+/// Collects files below `srcdir` matching `pattern`.
+///
+/// Only the directory below the pattern's literal prefix (e.g., `src` for
+/// `src/**/*.c`) is searched.
+fn find_matches(
+    srcdir: &Utf8Path,
+    source: &str,
+    pattern: &Pattern,
+    watched: &mut IndexSet<Utf8PathBuf>,
+    matches: &mut Vec<String>,
+) -> Result<()> {
+    let components: Vec<&str> = source.split('/').collect();
+    let literal = components
+        .iter()
+        .take_while(|component| !is_glob(component))
+        .count()
+        // the last component is the file name, never a directory
+        .min(components.len() - 1);
+    let base = Utf8PathBuf::from(components[..literal].join("/"));
+
+    // without `**`, only as many directory levels as the pattern has
+    let max_depth = if source.contains("**") {
+        usize::MAX
+    } else {
+        components.len() - literal - 1
+    };
+
+    let mut queue = vec![(base, 0)];
+    while let Some((dir, depth)) = queue.pop() {
+        let path = match srcdir.join(&dir) {
+            path if path.as_str().is_empty() => Utf8PathBuf::from("."),
+            path => path,
+        };
+        if !path.is_dir() {
+            // the base directory might be created later (e.g., by a
+            // generator), so watch the closest ancestor that exists instead
+            if depth == 0 {
+                if let Some(existing) = path.ancestors().skip(1).find(|dir| dir.is_dir()) {
+                    watched.insert(existing.to_path_buf());
+                } else if path.is_relative() {
+                    watched.insert(Utf8PathBuf::from("."));
+                }
+            }
+            continue;
+        }
+        watched.insert(path.clone());
+
+        for entry in path.read_dir_utf8()? {
+            let entry = entry?;
+            let relative = dir.join(entry.file_name());
+            if entry.file_type()?.is_dir() {
+                if depth < max_depth {
+                    queue.push((relative, depth + 1));
+                }
+            } else if pattern.matches_with(relative.as_str(), MATCH_OPTIONS) {
+                matches.push(relative.into_string());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand() {
+        let dir = tempfile::tempdir().unwrap();
+        let srcdir = Utf8Path::from_path(dir.path()).unwrap();
+        for file in [
+            "a.c",
+            "b.c",
+            "b.h",
+            "sub/c.c",
+            "sub/deeper/d.c",
+            "sub/deeper/e.c",
+        ] {
+            let path = srcdir.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "").unwrap();
+        }
+
+        let expand = |sources: &[&str]| {
+            let sources: Vec<String> = sources.iter().map(|s| s.to_string()).collect();
+            let mut watched = IndexSet::new();
+            let result = expand(srcdir, &sources, &mut IndexMap::new(), &mut watched).unwrap();
+            (result, watched.len())
+        };
+
+        assert_eq!(expand(&["*.c"]), (vec!["a.c".into(), "b.c".into()], 1));
+        assert_eq!(
+            expand(&["main.c", "sub/**/*.c", "!sub/deeper/e.c"]),
+            (
+                vec!["main.c".into(), "sub/c.c".into(), "sub/deeper/d.c".into()],
+                2
+            )
+        );
+        assert_eq!(
+            expand(&["sub/*/*.c"]),
+            (vec!["sub/deeper/d.c".into(), "sub/deeper/e.c".into()], 2)
+        );
+
+        // a missing base directory is watched through its closest ancestor
+        assert_eq!(expand(&["missing/*.c"]), (vec![], 1));
+        let mut watched = IndexSet::new();
+        let sources = vec!["sub/missing/*.c".to_string()];
+        super::expand(srcdir, &sources, &mut IndexMap::new(), &mut watched).unwrap();
+        assert_eq!(watched, IndexSet::from([srcdir.join("sub")]));
+    }
+}
@@ -1,12 +1,15 @@
 use anyhow::{anyhow, Error};
 use camino::{Utf8Path, Utf8PathBuf};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
+use crate::lockfile::Lockfile;
+
 mod cmd;
 mod download;
 mod local;
 
-#[derive(Debug, Serialize, Deserialize, Hash)]
+#[derive(Debug, Serialize, Deserialize, Hash, JsonSchema)]
 #[serde(untagged)]
 pub enum ImportEntry {
     Download(crate::download::Download),
@@ -15,11 +18,16 @@ pub enum ImportEntry {
 }
 
 impl ImportEntry {
-    pub fn handle<T: AsRef<Utf8Path>>(&self, build_dir: T) -> Result<Utf8PathBuf, Error> {
+    pub fn handle<T: AsRef<Utf8Path>>(
+        &self,
+        build_dir: T,
+        relpath: &Utf8Path,
+        lock: &mut Lockfile,
+    ) -> Result<Utf8PathBuf, Error> {
         match self {
-            Self::Download(download) => download.handle(build_dir),
-            Self::Command(command) => command.handle(build_dir),
-            Self::Local(local) => local.handle(build_dir),
+            Self::Download(download) => download.handle(build_dir, relpath, lock),
+            Self::Command(command) => command.handle(build_dir, relpath, lock),
+            Self::Local(local) => local.handle(build_dir, relpath, lock),
         }
     }
 }
@@ -27,7 +35,15 @@ impl ImportEntry {
 pub trait Import: std::hash::Hash {
     fn get_name(&self) -> Option<String>;
     fn get_dldir(&self) -> Option<&String>;
-    fn handle<T: AsRef<Utf8Path>>(&self, build_dir: T) -> Result<Utf8PathBuf, Error>;
+    /// Performs the import, returning the path of the imported laze file.
+    ///
+    /// `relpath` is the directory of the laze file containing the import.
+    fn handle<T: AsRef<Utf8Path>>(
+        &self,
+        build_dir: T,
+        relpath: &Utf8Path,
+        lock: &mut Lockfile,
+    ) -> Result<Utf8PathBuf, Error>;
     fn get_path<T: AsRef<Utf8Path>>(&self, build_dir: T) -> Result<Utf8PathBuf, Error> {
         use crate::utils::calculate_hash;
 
@@ -1,7 +1,8 @@
 use anyhow::{anyhow, Context as _};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize, Hash)]
+#[derive(Debug, Serialize, Deserialize, Hash, JsonSchema)]
 pub struct Command {
     name: Option<String>,
     command: String,
@@ -20,6 +21,8 @@ impl super::Import for Command {
     fn handle<T: AsRef<camino::Utf8Path>>(
         &self,
         build_dir: T,
+        _relpath: &camino::Utf8Path,
+        _lock: &mut crate::lockfile::Lockfile,
     ) -> Result<camino::Utf8PathBuf, anyhow::Error> {
         let path = self.get_path(&build_dir)?;
 
@@ -5,9 +5,12 @@ use camino::{Utf8Path, Utf8PathBuf};
 use git_cache::GitCacheClonerBuilder;
 use log::debug;
 use rust_embed::RustEmbed;
+use sha2::{Digest, Sha256};
 
 use super::Import;
 use crate::download::{Download, Git, Source};
+use crate::lockfile::Lockfile;
+use crate::offline;
 
 #[derive(RustEmbed)]
 #[folder = "assets/imports"]
@@ -35,19 +38,125 @@ fn git_clone_commit(url: &str, target_path: &Utf8Path, commit: &str) -> Result<(
 
 fn git_clone_branch(url: &str, target_path: &Utf8Path, branch: &str) -> Result<(), Error> {
     git_cloner(url, target_path)?
-        .update(true)
+        .update(!offline::enabled())
         .extra_clone_args(Some(vec!["--branch".into(), branch.into()]))
         .do_clone()
 }
 
+fn git_head(path: &Utf8Path) -> Result<String, Error> {
+    let output = std::process::Command::new("git")
+        .arg("-C")
+        .arg(path)
+        .arg("rev-parse")
+        .arg("HEAD")
+        .output()?;
+    if !output.status.success() {
+        return Err(anyhow!("git rev-parse failed"));
+    }
+    Ok(String::from_utf8(output.stdout)?.trim().to_string())
+}
+
+/// Records the commit that was checked out for `url` in the tagfile, so the
+/// checkout is reused once that commit is locked.
+fn lock_head(url: &str, target_path: &Utf8Path, tagfile: &Utf8Path) -> Result<String, Error> {
+    let commit = git_head(target_path)
+        .with_context(|| format!("getting checked out commit of \"{target_path}\""))?;
+    Source::Git(Git::Commit {
+        url: url.to_string(),
+        commit: commit.clone(),
+    })
+    .create_tagfile(tagfile)?;
+    Ok(commit)
+}
+
+/// Returns a hash over the contents of `patches`, which gets stored in the
+/// import's `.laze-patched` tagfile.
+fn patches_hash(patches: &[Utf8PathBuf]) -> Result<String, Error> {
+    let mut hasher = Sha256::new();
+    for patch in patches {
+        let contents = std::fs::read(patch).with_context(|| format!("reading patch {patch}"))?;
+        hasher.update(&contents);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+fn apply_patches(target_path: &Utf8Path, patches: &[Utf8PathBuf]) -> Result<(), Error> {
+    // for non-git imports, `git apply` would otherwise pick up a repository
+    // containing the build directory and apply the patches relative to that.
+    let target_path = target_path.canonicalize_utf8()?;
+    let ceiling = target_path.parent().unwrap();
+
+    for patch in patches {
+        debug!("IMPORT applying {patch} -> {target_path}");
+        let patch_path = patch
+            .canonicalize_utf8()
+            .with_context(|| format!("reading patch {patch}"))?;
+        let status = std::process::Command::new("git")
+            .env("GIT_CEILING_DIRECTORIES", ceiling)
+            .arg("-C")
+            .arg(&target_path)
+            .arg("apply")
+            .arg("--ignore-whitespace")
+            .arg(&patch_path)
+            .status()
+            .with_context(|| format!("applying patch {patch}"))?;
+        if !status.success() {
+            return Err(anyhow!(
+                "applying patch {patch} to \"{target_path}\" failed"
+            ));
+        }
+    }
+    Ok(())
+}
+
 impl Import for Download {
-    fn handle<T: AsRef<Utf8Path>>(&self, build_dir: T) -> Result<Utf8PathBuf, Error> {
+    fn handle<T: AsRef<Utf8Path>>(
+        &self,
+        build_dir: T,
+        relpath: &Utf8Path,
+        lock: &mut Lockfile,
+    ) -> Result<Utf8PathBuf, Error> {
         let target_path = self.get_path(build_dir).unwrap();
         let tagfile = target_path.join(".laze-downloaded");
+        let tagfile_patched = target_path.join(".laze-patched");
+
+        let patches = self.patch_paths(relpath);
+        let patches_hash = if patches.is_empty() {
+            None
+        } else {
+            Some(patches_hash(&patches)?)
+        };
+
+        // git imports following a branch or tag are checked out at the commit
+        // recorded in `laze.lock`, if there is one.
+        let mut commit = match &self.source {
+            Source::Git(git) => lock.commit(git)?.map(str::to_string),
+            _ => None,
+        };
+        let source = match (&self.source, &commit) {
+            (Source::Git(git), Some(commit)) => Source::Git(Git::Commit {
+                url: git.url().clone(),
+                commit: commit.clone(),
+            }),
+            _ => self.source.clone(),
+        };
+
+        // checkouts made before git imports were locked record the branch, tag
+        // or default branch instead of the commit. those are redone, so that
+        // the commit gets locked.
+        let unlocked = matches!(
+            source,
+            Source::Git(Git::Branch { .. } | Git::Tag { .. } | Git::Default { .. })
+        );
 
         let mut skip_download = false;
-        if tagfile.exists() {
-            skip_download = self.compare_with_tagfile(&tagfile).unwrap_or_default();
+        if tagfile.exists() && !unlocked {
+            skip_download = source.compare_with_tagfile(&tagfile).unwrap_or_default();
+        }
+        if skip_download {
+            // patches cannot be taken back, so a checkout that has different
+            // (or no) patches applied needs to be redone.
+            skip_download = std::fs::read_to_string(&tagfile_patched).ok() == patches_hash;
         }
         if !skip_download {
             if crate::cli::completing() {
@@ -58,12 +167,20 @@ impl Import for Download {
                 return Err(anyhow!("cannot download when completing"));
             }
 
+            if !offline::available(&source) {
+                return Err(offline::NotAvailable(format!(
+                    "import {}",
+                    offline::describe(&source)
+                ))
+                .into());
+            }
+
             if target_path.exists() {
                 remove_dir_all(&target_path)
                     .with_context(|| format!("removing path \"{target_path}\""))?;
             }
 
-            match &self.source {
+            match &source {
                 Source::Git(Git::Commit { url, commit }) => {
                     debug!("IMPORT Git {url}:{commit} -> {target_path}");
 
@@ -71,7 +188,7 @@ impl Import for Download {
                         format!("cloning git url: \"{url}\" commit: \"{commit}\"")
                     })?;
 
-                    self.create_tagfile(tagfile)?;
+                    source.create_tagfile(&tagfile)?;
                 }
                 Source::Git(Git::Branch {
                     url,
@@ -87,16 +204,26 @@ impl Import for Download {
                         format!("cloning git url: \"{url}\" branch/tag: \"{branch_or_tag}\"")
                     })?;
 
-                    self.create_tagfile(tagfile)?;
+                    commit = Some(lock_head(url, &target_path, &tagfile)?);
                 }
                 Source::Git(Git::Default { url }) => {
                     debug!("IMPORT Git {url} -> {target_path}");
 
                     git_cloner(url, &target_path)?
+                        .update(!offline::enabled())
                         .do_clone()
                         .with_context(|| format!("cloning git url: \"{url}\""))?;
 
-                    self.create_tagfile(tagfile)?;
+                    commit = Some(lock_head(url, &target_path, &tagfile)?);
+                }
+                Source::Archive(archive) => {
+                    debug!("IMPORT archive {} -> {target_path}", archive.url);
+
+                    archive
+                        .extract(&target_path)
+                        .with_context(|| format!("importing archive \"{}\"", archive.url))?;
+
+                    source.create_tagfile(&tagfile)?;
                 }
                 Source::Laze(name) => {
                     let mut at_least_one = false;
@@ -125,6 +252,16 @@ impl Import for Download {
                     }
                 }
             }
+
+            if let Some(patches_hash) = &patches_hash {
+                apply_patches(&target_path, &patches)?;
+                std::fs::write(&tagfile_patched, patches_hash)
+                    .with_context(|| format!("creating {tagfile_patched}"))?;
+            }
+        }
+
+        if let (Source::Git(git), Some(commit)) = (&self.source, &commit) {
+            lock.record(git, commit);
         }
 
         super::get_lazefile(&target_path)
@@ -142,6 +279,7 @@ impl Import for Download {
             | Source::Git(Git::Branch { url, .. })
             | Source::Git(Git::Tag { url, .. })
             | Source::Git(Git::Default { url, .. }) => url.split('/').next_back().map(|x| x.to_string()),
+            Source::Archive(archive) => Some(archive.file_name().to_string()),
             Source::Laze(name) => {
                 let prefix = format!("{}/", name);
 
@@ -1,12 +1,14 @@
 use anyhow::{anyhow, Context, Error};
 use camino::{Utf8Path, Utf8PathBuf};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use crate::serde_bool_helpers::default_as_false;
 
-#[derive(Debug, Serialize, Deserialize, Hash)]
+#[derive(Debug, Serialize, Deserialize, Hash, JsonSchema)]
 pub struct Local {
     name: Option<String>,
+    #[schemars(with = "String")]
     path: Utf8PathBuf,
     dldir: Option<String>,
     #[serde(default = "default_as_false")]
@@ -38,6 +40,8 @@ impl super::Import for Local {
     fn handle<T: AsRef<camino::Utf8Path>>(
         &self,
         build_dir: T,
+        _relpath: &camino::Utf8Path,
+        _lock: &mut crate::lockfile::Lockfile,
     ) -> Result<camino::Utf8PathBuf, anyhow::Error> {
         if self.symlink {
             let path = self.get_path(&build_dir)?;
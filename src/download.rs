@@ -1,45 +1,104 @@
 //! This module deals with "download:" directives
 
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
 use std::{borrow::Cow, path::Path};
 
 use anyhow::{anyhow, Result};
 use camino::{Utf8Path, Utf8PathBuf};
 use indexmap::IndexMap;
 use itertools::Itertools;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
+use crate::location::Locate;
+use crate::lockfile::Lockfile;
 use crate::nested_env::EnvMap;
+use crate::offline;
 
 use super::{ninja::NinjaBuildBuilder, Module, Rule};
 
 pub mod source {
+    use schemars::JsonSchema;
     use serde::{Deserialize, Serialize};
 
-    #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Hash)]
+    #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Hash, JsonSchema)]
     pub enum Source {
+        /// Clone a git repository.
         #[serde(rename = "git")]
         Git(Git),
+        /// Use laze files bundled with laze.
         #[serde(rename = "laze")]
         Laze(String),
+        /// Download and extract an archive (tarball or zip file).
+        #[serde(rename = "archive")]
+        Archive(Archive),
     }
 
-    #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Hash)]
+    #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Hash, JsonSchema)]
+    #[serde(deny_unknown_fields)]
+    pub struct Archive {
+        /// Where to download the archive from (e.g., `https://` or `file://`).
+        pub url: String,
+        /// SHA-256 checksum of the archive (as hex string).
+        pub sha256: String,
+        /// Strip this many leading path components when extracting.
+        #[serde(default)]
+        pub strip_components: usize,
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Hash, JsonSchema)]
     #[serde(untagged, deny_unknown_fields)]
     pub enum Git {
-        Commit { url: String, commit: String },
-        Branch { url: String, branch: String },
-        Tag { url: String, tag: String },
-        Default { url: String },
+        /// Check out a specific commit.
+        Commit {
+            /// Where to clone the repository from.
+            url: String,
+            /// Commit hash to check out.
+            commit: String,
+        },
+        /// Check out a branch. The commit it points to gets locked in `laze.lock`.
+        Branch {
+            /// Where to clone the repository from.
+            url: String,
+            /// Name of the branch to check out.
+            branch: String,
+        },
+        /// Check out a tag. The commit it points to gets locked in `laze.lock`.
+        Tag {
+            /// Where to clone the repository from.
+            url: String,
+            /// Name of the tag to check out.
+            tag: String,
+        },
+        /// Check out the default branch. Its commit gets locked in `laze.lock`.
+        Default {
+            /// Where to clone the repository from.
+            url: String,
+        },
+    }
+
+    impl Git {
+        pub fn url(&self) -> &String {
+            match self {
+                Self::Commit { url, .. }
+                | Self::Branch { url, .. }
+                | Self::Tag { url, .. }
+                | Self::Default { url } => url,
+            }
+        }
     }
 }
 
-pub use source::{Git, Source};
+pub use source::{Archive, Git, Source};
 
-#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Hash)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Hash, JsonSchema)]
 pub struct Download {
     #[serde(flatten)]
     pub source: source::Source,
+    /// Patch files to apply after download, relative to the defining laze file. Imports apply them to any source using `git apply`, module downloads only to git sources using `git am`.
     pub patches: Option<Vec<String>>,
+    /// Download directory, relative to `build/dl`. Defaults to `<relpath>/<module_name>`.
     pub dldir: Option<String>,
 }
 
@@ -64,6 +123,29 @@ impl Download {
         Utf8Path::new(srcdir).join(".laze-patched")
     }
 
+    /// Returns the paths of the patches, which are relative to `relpath`.
+    pub fn patch_paths(&self, relpath: &Utf8Path) -> Vec<Utf8PathBuf> {
+        self.patches
+            .iter()
+            .flatten()
+            .map(|patch| relpath.join(patch))
+            .collect()
+    }
+
+    /// Returns whether the download step already ran for this source.
+    ///
+    /// The download rules write the commit (or archive checksum) into the
+    /// tagfile, so a download of another version doesn't count.
+    pub fn is_downloaded(&self, srcdir: &Utf8PathBuf) -> bool {
+        let expected = match &self.source {
+            Source::Git(Git::Commit { commit, .. }) => commit,
+            Source::Archive(archive) => &archive.sha256,
+            _ => return false,
+        };
+        std::fs::read_to_string(self.tagfile_download(srcdir))
+            .is_ok_and(|contents| contents.trim() == expected)
+    }
+
     pub fn tagfile(&self, srcdir: &Utf8PathBuf) -> Utf8PathBuf {
         if self.patches.is_some() {
             self.tagfile_patched(srcdir)
@@ -73,10 +155,7 @@ impl Download {
     }
 
     pub fn create_tagfile<P: AsRef<Path>>(&self, path: P) -> Result<()> {
-        let path = path.as_ref();
-        let contents = serde_json::to_string(&self.source)?;
-        std::fs::write(path, contents)?;
-        Ok(())
+        self.source.create_tagfile(path)
     }
 
     fn render(
@@ -93,13 +172,19 @@ impl Download {
                 rule_env.insert("url".to_string(), url.to_string());
                 "GIT_DOWNLOAD"
             }
+            Source::Archive(archive) => {
+                rule_env.insert("url".to_string(), archive.url.to_string());
+                rule_env.insert("sha256".to_string(), archive.sha256.to_string());
+                rule_env.insert(
+                    "strip_components".to_string(),
+                    archive.strip_components.to_string(),
+                );
+                "ARCHIVE_DOWNLOAD"
+            }
             _ => return Err(anyhow!("unsupported download type")),
         };
 
-        let download_rule = match rules.values().find(|rule| rule.name == rulename) {
-            Some(x) => x,
-            None => panic!("missing {} rule for module {}", rulename, module.name),
-        };
+        let download_rule = find_rule(module, rules, rulename)?;
 
         let ninja_download_rule = download_rule.to_ninja(env)?;
 
@@ -143,10 +228,7 @@ impl Download {
             _ => return Err(anyhow!("unsupported download type for patching")),
         };
 
-        let patch_rule = match rules.values().find(|rule| rule.name == rulename) {
-            Some(x) => x,
-            None => panic!("missing {} rule for module {}", rulename, module.name),
-        };
+        let patch_rule = find_rule(module, rules, rulename)?;
 
         let ninja_patch_rule = patch_rule.to_ninja(env).unwrap();
 
@@ -178,6 +260,15 @@ impl Download {
             ninja_patch_build.to_string(),
         ])
     }
+}
+
+impl Source {
+    pub fn create_tagfile<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let contents = serde_json::to_string(self)?;
+        std::fs::write(path, contents)?;
+        Ok(())
+    }
 
     pub(crate) fn compare_with_tagfile<P: AsRef<Path>>(&self, tagfile: P) -> Result<bool> {
         let tagfile_contents = std::fs::read_to_string(tagfile.as_ref())?;
@@ -187,7 +278,7 @@ impl Download {
         // We don't care much for the error case or its reasons, as this is basically used for
         // caching.
         if let Ok(tagfile_source) = tagfile_source {
-            if tagfile_source == self.source {
+            if tagfile_source == *self {
                 return Ok(true);
             }
         } else {
@@ -197,13 +288,165 @@ impl Download {
     }
 }
 
+/// Resolves the git downloads of the modules that builds use.
+///
+/// Branches, tags and default branches are resolved to the commit locked in
+/// `laze.lock`, or to the commit they currently point to. Each of them is
+/// only looked up once, even if many modules or builds use it.
+pub struct Resolver {
+    state: Mutex<ResolverState>,
+}
+
+struct ResolverState {
+    lock: Lockfile,
+    resolved: HashMap<Git, Result<String, Arc<anyhow::Error>>>,
+}
+
+impl Resolver {
+    pub fn new(lock: Lockfile) -> Self {
+        Self {
+            state: Mutex::new(ResolverState {
+                lock,
+                resolved: HashMap::new(),
+            }),
+        }
+    }
+
+    /// Returns `download` with its git branch, tag or default branch resolved
+    /// to a commit.
+    pub fn resolve<'a>(&self, download: &'a Download) -> Result<Cow<'a, Download>> {
+        let Source::Git(git) = &download.source else {
+            return Ok(Cow::Borrowed(download));
+        };
+        if let Git::Commit { .. } = git {
+            return Ok(Cow::Borrowed(download));
+        }
+
+        let mut state = self.state.lock().unwrap();
+        let state = &mut *state;
+        let result = match state.resolved.get(git) {
+            Some(result) => result,
+            None => {
+                let result = match state.lock.commit(git) {
+                    Ok(Some(commit)) => Ok(commit.to_string()),
+                    Ok(None) => resolve_remote(git),
+                    Err(e) => Err(e),
+                };
+                if let Ok(commit) = &result {
+                    state.lock.record(git, commit);
+                }
+                state
+                    .resolved
+                    .entry(git.clone())
+                    .or_insert(result.map_err(Arc::new))
+            }
+        };
+
+        match result {
+            Ok(commit) => Ok(Cow::Owned(Download {
+                source: Source::Git(Git::Commit {
+                    url: git.url().clone(),
+                    commit: commit.clone(),
+                }),
+                ..download.clone()
+            })),
+            Err(e) => match e.downcast_ref::<offline::NotAvailable>() {
+                Some(missing) => Err(offline::NotAvailable(missing.0.clone()).into()),
+                None => Err(anyhow!("{e:#}")),
+            },
+        }
+    }
+
+    /// Updates `laze.lock` with the commits that were resolved, returns whether
+    /// it changed.
+    pub fn store(self) -> Result<bool> {
+        let mut lock = self.state.into_inner().unwrap().lock;
+        lock.keep_unresolved();
+        lock.store()
+    }
+}
+
+/// Returns the commit a git branch, tag or default branch currently points to.
+fn resolve_remote(git: &Git) -> Result<String> {
+    if offline::enabled() {
+        return offline::cached_commit(git).ok_or_else(|| {
+            offline::NotAvailable(offline::describe(&Source::Git(git.clone()))).into()
+        });
+    }
+
+    // for annotated tags, the peeled ref (`^{}`) points to the commit
+    let refs = match git {
+        Git::Branch { branch, .. } => vec![format!("refs/heads/{branch}")],
+        Git::Tag { tag, .. } => vec![format!("refs/tags/{tag}"), format!("refs/tags/{tag}^{{}}")],
+        Git::Default { .. } => vec!["HEAD".to_string()],
+        Git::Commit { commit, .. } => return Ok(commit.clone()),
+    };
+
+    // git runs from the top of an enclosing work tree, so relative paths to
+    // local repositories need to be made absolute
+    let url = match Utf8Path::new(git.url()).canonicalize_utf8() {
+        Ok(path) => path.into_string(),
+        Err(_) => git.url().clone(),
+    };
+
+    let output = std::process::Command::new("git")
+        .arg("ls-remote")
+        .arg("--")
+        .arg(&url)
+        .args(&refs)
+        .output()?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "git ls-remote {} failed: {}",
+            git.url(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    let stdout = String::from_utf8(output.stdout)?;
+    let commits: IndexMap<&str, &str> = stdout
+        .lines()
+        .filter_map(|line| line.split_once('\t'))
+        .map(|(commit, name)| (name, commit))
+        .collect();
+
+    refs.iter()
+        .rev()
+        .find_map(|name| commits.get(name.as_str()))
+        .map(|commit| commit.to_string())
+        .ok_or_else(|| anyhow!("{} not found in {}", refs[0], git.url()))
+}
+
+/// Returns the rule named `rulename`, which the builder has to provide for
+/// `module`'s download.
+fn find_rule<'a>(
+    module: &Module,
+    rules: &IndexMap<String, &'a Rule>,
+    rulename: &str,
+) -> Result<&'a Rule> {
+    rules
+        .values()
+        .find(|rule| rule.name == rulename)
+        .copied()
+        .ok_or_else(|| anyhow!("module \"{}\": no {rulename} rule available", module.name))
+        .located(
+            module
+                .location
+                .as_ref()
+                .and_then(|location| location.key("download"))
+                .or_else(|| module.location.clone())
+                .as_ref(),
+        )
+}
+
 pub fn handle_module(
     module: &Module,
+    download: Option<&Download>,
     build_dir: &Utf8Path,
     rules: &IndexMap<String, &Rule>,
     env: &EnvMap,
 ) -> Result<Option<Vec<String>>> {
-    if let Some(download) = &module.download {
+    if let Some(download) = download {
         Ok(Some(download.render(module, build_dir, rules, env)?))
     } else {
         Ok(None)
@@ -232,6 +475,6 @@ mod test {
 
         let tagfile = tmpdir.path().join("tagfile.json");
         download.create_tagfile(&tagfile).unwrap();
-        assert!(download.compare_with_tagfile(&tagfile).unwrap());
+        assert!(download.source.compare_with_tagfile(&tagfile).unwrap());
     }
 }
@@ -23,22 +23,31 @@ use serde::{Deserialize, Serialize};
 use solvent::DepGraph;
 
 use crate::{
-    build::Build,
+    build::{Build, ResolverResult},
     data::{load, FileTreeState},
     download,
+    location::{self, Locate},
+    lockfile::Lockfile,
     model::{BlockAllow, Rule},
     nested_env::{self, Env, EnvKey, IfMissing},
     ninja::{NinjaBuildBuilder, NinjaRule, NinjaRuleBuilder},
+    offline,
     utils::{self, ContainingPath},
     Context, ContextBag, ContextBagError, Dependency, Module, Task, TaskError,
 };
 
+/// How many rules may be applied to a source file one after another (e.g.,
+/// .proto -> .pb.c -> .o are two).
+const MAX_RULE_CHAIN_LEN: usize = 8;
+
 #[derive(Deserialize, Serialize, Debug)]
 pub struct BuildInfo {
     pub binary: String,
     pub builder: String,
     pub tasks: IndexMap<String, Result<Task, TaskError>>,
     pub out: Utf8PathBuf,
+    /// files created from `out` by artifact rules, by artifact name
+    pub artifacts: IndexMap<String, Utf8PathBuf>,
 
     #[serde(skip)]
     pub module_info: Option<IndexMap<String, ModuleInfo>>,
@@ -96,6 +105,34 @@ fn relroot(relpath: &Utf8Path) -> Utf8PathBuf {
     }
 }
 
+/// Creates the environment laze provides to all builds.
+pub(crate) fn laze_env(project_root: &Utf8Path, build_dir: &Utf8Path) -> Env {
+    let mut laze_env = Env::new();
+    laze_env.insert("in".to_string(), "\\${in}");
+    laze_env.insert("out".to_string(), "\\${out}");
+    laze_env.insert("build-dir".to_string(), build_dir);
+    laze_env.insert("outfile".to_string(), "${bindir}/${app}.elf");
+    laze_env.insert("project-root".to_string(), project_root);
+    laze_env.insert("root".to_string(), ".");
+
+    // some useful variables
+    laze_env.insert("host::tuple".to_string(), env!("TARGET_TRIPLE"));
+    laze_env.insert("host::os".to_string(), std::env::consts::OS);
+    laze_env.insert("host::arch".to_string(), std::env::consts::ARCH);
+    laze_env.insert("host::family".to_string(), std::env::consts::FAMILY);
+
+    // make our binary path available, used by e.g., the default download rules.
+    laze_env.insert(
+        "LAZE_BIN".to_string(),
+        std::env::current_exe()
+            .unwrap()
+            .to_str()
+            .expect("UTF-8 binary name for laze"),
+    );
+
+    laze_env
+}
+
 #[derive(Builder)]
 #[builder(setter(into))]
 pub struct Generator {
@@ -132,6 +169,7 @@ impl Generator {
         }
 
         let (contexts, treestate, load_stats) = load(&self.project_file, &self.build_dir)?;
+        let downloads = download::Resolver::new(Lockfile::load(&self.project_file)?);
 
         trace!(
             "laze: parsing {} files took {:?}",
@@ -166,30 +204,7 @@ impl Generator {
 
         let start = Instant::now();
 
-        let mut laze_env = Env::new();
-        laze_env.insert("in".to_string(), "\\${in}");
-        laze_env.insert("out".to_string(), "\\${out}");
-        laze_env.insert("build-dir".to_string(), self.build_dir.clone());
-        laze_env.insert("outfile".to_string(), "${bindir}/${app}.elf");
-        laze_env.insert("project-root".to_string(), self.project_root.clone());
-        laze_env.insert("root".to_string(), ".");
-
-        // some useful variables
-        laze_env.insert("host::tuple".to_string(), env!("TARGET_TRIPLE"));
-        laze_env.insert("host::os".to_string(), std::env::consts::OS);
-        laze_env.insert("host::arch".to_string(), std::env::consts::ARCH);
-        laze_env.insert("host::family".to_string(), std::env::consts::FAMILY);
-
-        // make our binary path available, used by e.g., the default download rules.
-        laze_env.insert(
-            "LAZE_BIN".to_string(),
-            std::env::current_exe()
-                .unwrap()
-                .to_str()
-                .expect("UTF-8 binary name for laze"),
-        );
-
-        let laze_env = laze_env;
+        let laze_env = laze_env(&self.project_root, &self.build_dir);
 
         let selected_builders = match &self.builders {
             Selector::All => contexts.builders_vec(),
@@ -289,6 +304,7 @@ impl Generator {
                     self.require.as_ref(),
                     &self.cli_env.as_ref(),
                     self.collect_insights,
+                    &downloads,
                 )
                 .with_context(|| format!("binary \"{}\"", bin.name))
                 .with_context(|| format!("builder \"{}\"", builder.name))
@@ -322,9 +338,15 @@ impl Generator {
             start.elapsed()
         );
 
+        // if `laze.lock` changed only now, the tree state (taken while loading)
+        // does not know it, so the result must not be cached.
+        let lock_changed = downloads.store()?;
+
         let build_dir = self.build_dir.clone();
         let result = GenerateResult::new(self, builds, treestate);
-        result.to_cache(&build_dir)?;
+        if !lock_changed {
+            result.to_cache(&build_dir)?;
+        }
         Ok(result)
     }
 }
@@ -332,7 +354,7 @@ impl Generator {
 type NinjaRuleSnippets = IndexSet<String>;
 
 #[derive(Default)]
-enum NoBuildReason {
+pub(crate) enum NoBuildReason {
     #[default]
     Unknown,
     Msg(String),
@@ -354,9 +376,8 @@ impl NoBuildReason {
 }
 
 #[allow(clippy::large_enum_variant)]
-enum ConfigureBuildResult {
+pub(crate) enum ConfigureBuildResult {
     Build(BuildInfo, NinjaRuleSnippets),
-    #[allow(dead_code)]
     NoBuild(NoBuildReason),
 }
 
@@ -366,22 +387,15 @@ impl From<NoBuildReason> for ConfigureBuildResult {
     }
 }
 
-// This function "renders" a specific app/builder pair, if dependencies
-// and block/allowlists allow it.
-//
-// TODO: configure_build() is approaching 300 LoC.  it should be split up.
-#[allow(clippy::too_many_arguments)]
-fn configure_build(
+/// Checks whether `binary` may be built for `builder`, according to the app's
+/// block/allowlists and context ancestry.
+///
+/// Returns the reason if it may not.
+pub(crate) fn check_build_allowed(
     binary: &Module,
     contexts: &ContextBag,
     builder: &Context,
-    laze_env: &Env,
-    select: Option<&Vec<Dependency<String>>>,
-    disable: Option<&Vec<String>>,
-    require: Option<&Vec<String>>,
-    cli_env: &Option<&Env>,
-    collect_insights: bool,
-) -> Result<ConfigureBuildResult> {
+) -> Option<NoBuildReason> {
     let mut reason = NoBuildReason::default();
 
     if !match contexts.is_allowed(builder, &binary.blocklist, &binary.allowlist) {
@@ -413,7 +427,7 @@ fn configure_build(
         }
     } {
         trace!("{}", reason);
-        return Ok(reason.into());
+        return Some(reason);
     }
 
     if let crate::model::IsAncestor::No =
@@ -426,46 +440,25 @@ fn configure_build(
             contexts.context_by_id(binary.context_id.unwrap()).name,
         ));
         trace!("{}", reason);
-        return Ok(reason.into());
-    }
-
-    debug!("configuring {} for {}", binary.name, builder.name);
-
-    // create build instance (binary A for builder X)
-    let build = Build::new(binary, builder, contexts, select);
-
-    // get build dir from laze_env
-    let build_dir = match laze_env.get("build-dir").unwrap() {
-        nested_env::EnvKey::Single(path) => Utf8PathBuf::from(path),
-        _ => unreachable!(),
-    };
-
-    // collect disabled modules from app and build context
-    let mut disabled_modules = build.build_context.collect_disabled_modules(contexts);
-
-    // collect modules disabled on CLI
-    if let Some(disable) = disable {
-        disabled_modules.extend(disable.iter().cloned());
+        return Some(reason);
     }
 
-    // collect modules "required" by CLI
-    let required_modules: IndexSet<String> = require.into_iter().flatten().cloned().collect();
-
-    // resolve all dependency names to specific modules.
-    // this also determines if all dependencies are met
-    let resolved = match build.resolve_selects(disabled_modules, required_modules) {
-        Err(e) => {
-            reason.msg(format!("laze: not building {:?}", e));
-            debug!("{}", reason);
-            return Ok(reason.into());
-        }
-        Ok(val) => val,
-    };
+    None
+}
 
-    // collect build context rules
-    let mut rules = IndexMap::new();
-    let rules = build.build_context.collect_rules(contexts, &mut rules);
-    let merge_opts = &builder.var_options;
+/// Creates the global environment of a build, before flattening.
+///
+/// This contains the laze and build context environments, all `global` module
+/// environments, some laze provided variables (`relpath`, `modules`, ...) and
+/// finally, CLI overrides.
+pub(crate) fn global_env(
+    laze_env: &Env,
+    build: &Build,
+    resolved: &ResolverResult,
+    contexts: &ContextBag,
+    cli_env: Option<&Env>,
+) -> Env {
+    let (binary, builder) = (build.binary(), build.builder());
 
     // create initial build context global env.
     let mut global_env = laze_env.clone();
@@ -518,6 +511,80 @@ fn configure_build(
         global_env.merge(cli_env);
     }
 
+    global_env
+}
+
+// This function "renders" a specific app/builder pair, if dependencies
+// and block/allowlists allow it.
+//
+// TODO: configure_build() is approaching 300 LoC.  it should be split up.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn configure_build(
+    binary: &Module,
+    contexts: &ContextBag,
+    builder: &Context,
+    laze_env: &Env,
+    select: Option<&Vec<Dependency<String>>>,
+    disable: Option<&Vec<String>>,
+    require: Option<&Vec<String>>,
+    cli_env: &Option<&Env>,
+    collect_insights: bool,
+    downloads: &download::Resolver,
+) -> Result<ConfigureBuildResult> {
+    if let Some(reason) = check_build_allowed(binary, contexts, builder) {
+        return Ok(reason.into());
+    }
+
+    debug!("configuring {} for {}", binary.name, builder.name);
+
+    // create build instance (binary A for builder X)
+    let build = Build::new(binary, builder, contexts, select);
+
+    // get build dir from laze_env
+    let build_dir = match laze_env.get("build-dir").unwrap() {
+        nested_env::EnvKey::Single(path) => Utf8PathBuf::from(path),
+        _ => unreachable!(),
+    };
+
+    // collect disabled modules from app and build context
+    let mut disabled_modules = build.build_context.collect_disabled_modules(contexts);
+
+    // collect modules disabled on CLI
+    if let Some(disable) = disable {
+        disabled_modules.extend(disable.iter().cloned());
+    }
+
+    // collect modules "required" by CLI
+    let required_modules: IndexSet<String> = require.into_iter().flatten().cloned().collect();
+
+    // resolve all dependency names to specific modules.
+    // this also determines if all dependencies are met
+    let resolved = match build.resolve_selects(disabled_modules, required_modules) {
+        Err(e) => {
+            let reason = NoBuildReason::Msg(match location::find(&e) {
+                Some(location) => format!("{e:#} ({location})"),
+                None => format!("{e:#}"),
+            });
+            debug!("laze: not building: {}", reason);
+            return Ok(reason.into());
+        }
+        Ok(val) => val,
+    };
+
+    // collect build context rules
+    let mut rules = IndexMap::new();
+    let rules = build.build_context.collect_rules(contexts, &mut rules);
+
+    // modules may bring their own rules. only if any does, the rules need to be
+    // collected per module.
+    let have_module_rules = resolved
+        .modules
+        .values()
+        .any(|module| module.rules.is_some());
+    let merge_opts = &builder.var_options;
+
+    let global_env = global_env(laze_env, &build, &resolved, contexts, *cli_env);
+
     let out_str = "out".to_string();
     let mut global_env_flattened = global_env
         .flatten_with_opts_option(merge_opts.as_ref())
@@ -534,6 +601,25 @@ fn configure_build(
     // vector collecting objects, later used as linking inputs
     let mut objects = Vec::new();
 
+    // whether modules are archived if they don't say
+    let archive_default = build
+        .build_context
+        .context_iter(contexts)
+        .find_map(|context| context.archive)
+        .unwrap_or(false);
+
+    // static libraries of modules with `archive: true` and prebuilt `libs`,
+    // keyed by the position of the module in link order. a module's prebuilt
+    // `libs` come after its own archive, as its code uses them. archives of
+    // modules with `whole_archive: true` are passed to LINK as
+    // `${whole_archives}`.
+    let link_order = resolved.link_order();
+    let mut archives = Vec::new();
+    let mut whole_archives = Vec::new();
+
+    // files the link step depends on, without passing them in `${in}`
+    let mut module_link_deps = Vec::new();
+
     // set containing ninja build or rule blocks
     let mut ninja_entries = IndexSet::new();
 
@@ -604,7 +690,7 @@ fn configure_build(
                 }
             }
             Err(_) => {
-                reason.msg(format!(
+                let reason = NoBuildReason::Msg(format!(
                     "error: {} for {}: build dependency cycle detected.",
                     binary.name, builder.name
                 ));
@@ -616,6 +702,8 @@ fn configure_build(
 
     let mut module_build_dep_files: IndexMap<&String, IndexSet<Utf8PathBuf>> = IndexMap::new();
     let mut download_dirs = IndexMap::new();
+    let mut missing_offline = Vec::new();
+    let mut failed_downloads = Vec::new();
 
     let mut module_info = collect_insights.then_some(IndexMap::new());
 
@@ -642,8 +730,40 @@ fn configure_build(
             .flatten_with_opts_option(merge_opts.as_ref())
             .with_context(|| format!("module \"{}\"", module.name))?;
 
+        // git downloads following a branch or tag get pinned to a commit
+        let download = match module.download.as_ref().map(|d| downloads.resolve(d)) {
+            None => None,
+            Some(Ok(download)) => Some(download),
+            Some(Err(e)) => {
+                match e.downcast::<offline::NotAvailable>() {
+                    Ok(missing) => {
+                        missing_offline.push(format!("module \"{}\": {}", module.name, missing.0))
+                    }
+                    Err(e) => failed_downloads.push(format!("module \"{}\": {e:#}", module.name)),
+                }
+                continue;
+            }
+        };
+
+        // in offline mode, downloads must already be done or be in the git cache
+        if let Some(download) = download.as_deref() {
+            if !download.is_downloaded(srcdir) && !offline::available(&download.source) {
+                missing_offline.push(format!(
+                    "module \"{}\": {}",
+                    module.name,
+                    offline::describe(&download.source)
+                ));
+            }
+        }
+
         // handle possible remote sources
-        let download_rules = download::handle_module(module, &build_dir, rules, &flattened_env)?;
+        let download_rules = download::handle_module(
+            module,
+            download.as_deref(),
+            &build_dir,
+            rules,
+            &flattened_env,
+        )?;
 
         if let Some(mut download_rules) = download_rules {
             ninja_entries.extend(download_rules.drain(..));
@@ -749,6 +869,35 @@ fn configure_build(
             .as_ref()
             .map_or(0, utils::calculate_hash);
 
+        // prebuilt libraries and other link inputs, relative to srcdir
+        for (file, is_lib) in (module.libs.iter().map(|lib| (lib, true)))
+            .chain(module.link_deps.iter().map(|dep| (dep, false)))
+        {
+            let path = Utf8PathBuf::from(
+                nested_env::expand_eval(srcdir.join(file), &flattened_env, IfMissing::Empty)
+                    .with_context(|| format!("module \"{}\"", module.name))?,
+            );
+
+            // downloaded files are created by the download step
+            if local_build_deps.is_some() {
+                let build = NinjaBuildBuilder::default()
+                    .rule("phony")
+                    .deps(local_build_deps.clone())
+                    .out(path.as_path())
+                    .build()
+                    .unwrap();
+                ninja_entries.insert(format!("{build}"));
+            } else if let Some(tagfile) = src_tagfile {
+                ninja_entries.insert(crate::ninja::alias(tagfile.as_str(), path.as_str()));
+            }
+
+            if is_lib {
+                archives.push(((link_order.get_index_of(module.name.as_str()), 1), path));
+            } else {
+                module_link_deps.push(path);
+            }
+        }
+
         if let Some(build) = &module.build {
             // module has custom build rule
 
@@ -838,46 +987,119 @@ fn configure_build(
         } else {
             // module is using the default build rule
 
-            // map extension -> rule for this module
-            let mut module_rules: IndexMap<String, NinjaRule> = IndexMap::new();
+            // add the rules of this module and of the modules it imports
+            let module_rules_map;
+            let rules: &IndexMap<String, &Rule> = if have_module_rules {
+                let mut map = rules.clone();
+                module.collect_rules(&resolved, &mut map)?;
+                module_rules_map = map;
+                &module_rules_map
+            } else {
+                rules
+            };
+
+            // map source -> rules, in the order they are applied
+            let mut source_rules: IndexMap<&String, Vec<&Rule>> = IndexMap::new();
+            // map rule name -> ninja rule, expanded with this module's env
+            let mut module_rules: IndexMap<&str, NinjaRule> = IndexMap::new();
+            // map (source, rule name) -> ninja rule, for sources with their own env
+            let mut source_ninja_rules: IndexMap<(&String, &str), NinjaRule> = IndexMap::new();
 
             // apply rules to sources
-            // BUG01: ext is taken *before* variable substitution
+            // BUG01: the rule is chosen *before* variable substitution
             for source in module.sources.iter().chain(optional_sources.iter()) {
-                let ext = Utf8Path::new(&source).extension().ok_or_else(|| {
-                    anyhow!(format!(
-                        "\"{}\": module \"{:?}\": source file \"{}\" missing extension",
-                        module.defined_in.as_ref().unwrap(),
-                        &module.name,
-                        &source
-                    ))
-                })?;
-
-                // This block finds a rule for this source file's extension
-                // (e.g., .c -> CC).
-                // If there is one, use it, otherwise create a new one from the
-                // context rules, applying this module's env.
-                module_rules.entry(ext.into()).or_insert({
-                    let rule = rules.get(ext).ok_or_else(|| {
-                        anyhow!(
-                            "no rule found for \"{}\" of module \"{}\" (from {})",
-                            source,
-                            module.name,
-                            module.defined_in.as_ref().unwrap(),
-                        )
-                    })?;
-
-                    let rule = rule
-                        .to_ninja(&flattened_env)
-                        .with_context(|| format!("while expanding cmd \"{}\"", rule.cmd))
-                        .with_context(|| format!("rule \"{}\"", rule.name))
-                        .with_context(|| format!("module \"{}\"", module.name))?;
-
-                    ninja_entries.insert(format!("{rule}"));
-                    rule
-                });
+                let location = module
+                    .location
+                    .as_ref()
+                    .map(|location| location.entry(&["sources"], source));
+                let source_override = module.source_overrides.get(source);
+
+                // use a rule explicitly chosen for the source or module,
+                // otherwise the one matching the source file's extension or
+                // name (e.g., .c -> CC).
+                let explicit_rule = match source_override.and_then(|o| o.rule.as_ref()) {
+                    Some(rule_name) => Some((rule_name, location.clone())),
+                    None => module.rule.as_ref().map(|rule_name| {
+                        let location = module.location.as_ref().and_then(|l| l.key("rule"));
+                        (rule_name, location)
+                    }),
+                };
+                let rule = match explicit_rule {
+                    Some((rule_name, rule_location)) => get_rule(rule_name, rules)
+                        .with_context(|| {
+                            format!("source \"{source}\" of module \"{}\"", module.name)
+                        })
+                        .located(rule_location.as_ref())?,
+                    None => Rule::find_by_input(rules, source)
+                        .ok_or_else(|| match Utf8Path::new(source).extension() {
+                            None => anyhow!(
+                                "module \"{}\": source file \"{}\" missing extension",
+                                &module.name,
+                                &source
+                            ),
+                            Some(_) => anyhow!(
+                                "no rule found for \"{}\" of module \"{}\"",
+                                source,
+                                module.name,
+                            ),
+                        })
+                        .located(location.as_ref())?,
+                };
+                let describe_source =
+                    || format!("source \"{source}\" of module \"{}\"", module.name);
+                if rule.out.is_none() {
+                    return Err(anyhow!("rule \"{}\" has no \"out\" extension", rule.name))
+                        .with_context(describe_source)
+                        .located(location.as_ref());
+                }
+
+                // the output might be a source for another rule (e.g.,
+                // .proto -> .pb.c -> .o), so follow those
+                let chain = Rule::chain(rules, rule, source, MAX_RULE_CHAIN_LEN)
+                    .ok_or_else(|| anyhow!("more than {MAX_RULE_CHAIN_LEN} rules in a row apply"))
+                    .with_context(describe_source)
+                    .located(location.as_ref())?;
+
+                let merged_env;
+                let source_env = match source_override.and_then(|o| o.env.as_ref()) {
+                    Some(env) => {
+                        // the file's env goes on top of the module's
+                        let mut source_env = module_env.clone();
+                        source_env.merge(env);
+                        merged_env = source_env;
+                        let source_env = merged_env
+                            .flatten_with_opts_option(merge_opts.as_ref())
+                            .with_context(describe_source)?;
+                        Some(source_env)
+                    }
+                    None => None,
+                };
+
+                for rule in &chain {
+                    let expand_rule = |env: &nested_env::EnvMap| {
+                        rule.to_ninja(env)
+                            .with_context(|| format!("while expanding cmd \"{}\"", rule.cmd))
+                            .with_context(|| format!("rule \"{}\"", rule.name))
+                            .located(rule.location.as_ref())
+                            .with_context(|| format!("module \"{}\"", module.name))
+                    };
+
+                    if let Some(source_env) = &source_env {
+                        let ninja_rule = expand_rule(source_env)?;
+                        ninja_entries.insert(format!("{ninja_rule}"));
+                        source_ninja_rules.insert((source, &rule.name), ninja_rule);
+                    } else if !module_rules.contains_key(rule.name.as_str()) {
+                        let ninja_rule = expand_rule(&flattened_env)?;
+                        ninja_entries.insert(format!("{ninja_rule}"));
+                        module_rules.insert(&rule.name, ninja_rule);
+                    }
+                }
+
+                source_rules.insert(source, chain);
             }
 
+            let module_objects_start = objects.len();
+
             // now for each source file,
             for source in module.sources.iter().chain(optional_sources.iter()) {
                 // 1. determine full file path (relative to project root)
@@ -889,47 +1111,67 @@ fn configure_build(
                     nested_env::expand_eval(srcpath, &flattened_env, IfMissing::Empty).unwrap(),
                 );
 
-                // 2. find ninja rule by lookup of the source file's extension
-                let ext = srcpath.extension().unwrap();
-
-                let rule = rules.get(ext).unwrap();
-
-                let ninja_rule = module_rules.get(ext).unwrap();
-                let rule_hash = ninja_rule.get_hash(None);
+                // 2. for each of the rules chosen above, build the output of
+                // the previous one (or the source file, for the first)
+                let chain = source_rules.get(source).unwrap();
+                let mut input = srcpath.clone();
+                let mut out = srcpath.clone();
+                // hash of the rules applied so far, so that outputs of a chain
+                // differ if any of its rules differs
+                let mut chain_hash = None;
+                for (i, rule) in chain.iter().enumerate() {
+                    let ninja_rule = source_ninja_rules
+                        .get(&(source, rule.name.as_str()))
+                        .or_else(|| module_rules.get(rule.name.as_str()))
+                        .unwrap();
+                    let rule_hash = ninja_rule.get_hash(chain_hash);
+                    chain_hash = Some(rule_hash);
+
+                    // 3. determine output path (e.g., name of C object file)
+                    let out_ext = rule.out.as_ref().unwrap();
+                    let is_last = i + 1 == chain.len();
+                    let mut object = objdir.clone();
+                    if !rule.shareable {
+                        out.set_extension(out_ext);
+                        object.push(&builder.name);
+                        object.push(&binary.name);
+                        object.push(&out);
+                    } else if is_last {
+                        // e.g., `foo.c` -> `objects/foo.<hash>.o`
+                        out.set_extension(format!("{}.{out_ext}", rule_hash ^ build_deps_hash));
+                        object.push(&out);
+                    } else {
+                        // generated sources go to a directory named after the
+                        // hash, e.g., `msg.proto` -> `objects/<hash>/msg.pb.c`,
+                        // so the names of later outputs don't pile up hashes
+                        out.set_extension(out_ext);
+                        object.push((rule_hash ^ build_deps_hash).to_string());
+                        object.push(&out);
+                    }
 
-                // 3. determine output path (e.g., name of C object file)
-                let out_ext = if rule.shareable {
-                    &format!(
-                        "{}.{}",
-                        rule_hash ^ build_deps_hash,
-                        &rule.out.as_ref().unwrap()
-                    )
-                } else {
-                    rule.out.as_ref().unwrap()
-                };
+                    // 4. render ninja "build:" snippet and add to this build's
+                    // ninja statement set
+                    let build = NinjaBuildBuilder::from_rule(ninja_rule)
+                        .input(Cow::from(input.as_path()))
+                        .deps(combined_build_deps.clone())
+                        .out(object.as_path())
+                        .build()
+                        .unwrap();
 
-                let out = srcpath.with_extension(out_ext);
+                    ninja_entries.insert(format!("{build}"));
 
-                let mut object = objdir.clone();
-                if !rule.shareable {
-                    object.push(&builder.name);
-                    object.push(&binary.name);
+                    // 5. store the output in this build's output list, or, if
+                    // it is a generated source, export it as build dependency
+                    if is_last {
+                        objects.push(object);
+                        break;
+                    }
+                    module_build_dep_files
+                        .entry(&module.name)
+                        .or_insert_with(IndexSet::new)
+                        .insert(object.clone());
+                    input = object;
                 }
-                object.push(out);
-
-                // 4. render ninja "build:" snippet and add to this build's
-                // ninja statement set
-                let build = NinjaBuildBuilder::from_rule(ninja_rule)
-                    .input(Cow::from(srcpath.as_path()))
-                    .deps(combined_build_deps.clone())
-                    .out(object.as_path())
-                    .build()
-                    .unwrap();
-
-                ninja_entries.insert(format!("{build}"));
-
-                // 5. store the output in this build's output list
-                objects.push(object);
 
                 // 6. optionally create dependency to the download / patch step
                 // TODO OPT: don't create one build entry per file, but one for
@@ -952,9 +1194,80 @@ fn configure_build(
                     }
                 }
             }
+
+            // optionally, archive this module's objects into a static library
+            // that gets linked instead
+            let archive = match module.archive {
+                Some(archive) => archive,
+                // apps' objects would not get pulled from an archive
+                None => archive_default && !module.is_binary,
+            };
+            if (archive || module.whole_archive) && objects.len() > module_objects_start {
+                let module_objects = objects.split_off(module_objects_start);
+
+                let ar_rule = get_rule("AR", rules)
+                    .with_context(|| format!("archiving module \"{}\"", module.name))
+                    .located(module.location.as_ref())?;
+                let ninja_ar_rule = ar_rule
+                    .to_ninja(&flattened_env)
+                    .with_context(|| format!("while expanding cmd \"{}\"", ar_rule.cmd))
+                    .with_context(|| format!("rule \"{}\"", ar_rule.name))
+                    .located(ar_rule.location.as_ref())
+                    .with_context(|| format!("module \"{}\"", module.name))?;
+
+                // name the archive after the module. if shareable, include a
+                // hash of the rule and objects, so apps with identical inputs
+                // share it.
+                let lib_name = module.name.replace(
+                    |c: char| !(c.is_ascii_alphanumeric() || "_-.".contains(c)),
+                    "_",
+                );
+                let mut archive = objdir.clone();
+                if ar_rule.shareable {
+                    let hash =
+                        utils::calculate_hash(&(ninja_ar_rule.get_hash(None), &module_objects));
+                    archive.push(format!("lib{lib_name}.{hash}.a"));
+                } else {
+                    archive.push(&builder.name);
+                    archive.push(&binary.name);
+                    archive.push(format!("lib{lib_name}.a"));
+                }
+
+                let build = NinjaBuildBuilder::from_rule(&ninja_ar_rule)
+                    .inputs(
+                        module_objects
+                            .iter()
+                            .map(|object| Cow::from(object.as_path()))
+                            .collect_vec(),
+                    )
+                    .out(archive.as_path())
+                    .build()
+                    .unwrap();
+
+                ninja_entries.insert(format!("{ninja_ar_rule}"));
+                ninja_entries.insert(format!("{build}"));
+
+                let index = (link_order.get_index_of(module.name.as_str()), 0);
+                if module.whole_archive {
+                    whole_archives.push((index, archive));
+                } else {
+                    archives.push((index, archive));
+                }
+            }
         }
     }
 
+    if !missing_offline.is_empty() {
+        return Err(offline::missing_error(&missing_offline));
+    }
+
+    if !failed_downloads.is_empty() {
+        return Err(anyhow!(
+            "resolving downloads failed:\n  - {}",
+            failed_downloads.join("\n  - ")
+        ));
+    }
+
     let global_build_dep_files = {
         let mut res = IndexSet::new();
         for dep in global_build_deps {
@@ -969,9 +1282,18 @@ fn configure_build(
         }
     };
 
+    // archives are linked after the objects, each before the archives it
+    // depends on, as the linker needs.
+    archives.sort_by_key(|(index, _)| *index);
+    whole_archives.sort_by_key(|(index, _)| *index);
+
     // NinjaBuildBuilder expects a Vec<&Utf8Path>, but the loop above creates a Vec<Utf8PathBuf>.
     // thus, convert.
-    let objects: Vec<_> = objects.iter().map(|x| Cow::from(x.as_ref())).collect();
+    let objects: Vec<_> = objects
+        .iter()
+        .chain(archives.iter().map(|(_, archive)| archive))
+        .map(|x| Cow::from(x.as_ref()))
+        .collect();
 
     fn get_rule<'a>(rule_name: &str, rules: &'a IndexMap<String, &Rule>) -> Result<&'a Rule> {
         let rule = rules
@@ -983,21 +1305,131 @@ fn configure_build(
 
     // linking
     {
-        let ninja_link_rule = get_rule("LINK", rules)?
+        let link_rule = get_rule("LINK", rules).located(builder.location.as_ref())?;
+        let ninja_link_rule = link_rule
             .to_ninja(&global_env_flattened)
             .context("rendering LINK rule")?;
+        // whole archives are passed using a variable, as the linker usually
+        // needs flags around them
+        let mut link_vars = IndexMap::new();
+        let mut link_deps = global_build_dep_files;
+        if !module_link_deps.is_empty() {
+            link_deps
+                .get_or_insert_with(Vec::new)
+                .extend(module_link_deps.iter().map(|x| Cow::from(x.as_path())));
+        }
+        if !whole_archives.is_empty() {
+            let uses_whole_archives = |s: &str| s.contains("${whole_archives}");
+            if !(uses_whole_archives(&link_rule.cmd)
+                || link_rule
+                    .rspfile_content
+                    .as_deref()
+                    .is_some_and(uses_whole_archives))
+            {
+                return Err(anyhow!(
+                    "LINK rule does not use \"${{whole_archives}}\", needed by modules with \"whole_archive: true\""
+                ))
+                .located(builder.location.as_ref());
+            }
+            link_vars.insert(
+                "whole_archives".to_string(),
+                whole_archives.iter().map(|(_, archive)| archive).join(" "),
+            );
+            link_deps
+                .get_or_insert_with(Vec::new)
+                .extend(whole_archives.iter().map(|(_, x)| Cow::from(x.as_path())));
+        }
+
         // build ninja link target
-        let ninja_link_build = NinjaBuildBuilder::from_rule(&ninja_link_rule)
+        let mut ninja_link_build = NinjaBuildBuilder::from_rule(&ninja_link_rule);
+        ninja_link_build
             .inputs(objects)
-            .deps(global_build_dep_files)
-            .out(outfile.as_path())
-            .build()
-            .unwrap();
+            .deps(link_deps)
+            .out(outfile.as_path());
+        if !link_vars.is_empty() {
+            ninja_link_build.env(&link_vars);
+        }
+        let ninja_link_build = ninja_link_build.build().unwrap();
 
         ninja_entries.insert(format!("{}", ninja_link_rule));
         ninja_entries.insert(format!("{}", ninja_link_build));
     }
 
+    // artifacts, created from the linked app (or another artifact)
+    let mut artifacts: IndexMap<String, Utf8PathBuf> = IndexMap::new();
+    {
+        let mut pending = rules
+            .values()
+            .copied()
+            .filter(|rule| rule.artifact.is_some())
+            .unique_by(|rule| &rule.name)
+            .collect_vec();
+
+        // artifacts created from others need to wait for those
+        while !pending.is_empty() {
+            let (ready, waiting): (Vec<&Rule>, Vec<&Rule>) =
+                pending
+                    .into_iter()
+                    .partition(|rule| match &rule.artifact_from {
+                        Some(from) => artifacts.contains_key(from),
+                        None => true,
+                    });
+            if ready.is_empty() {
+                let rule = waiting[0];
+                return Err(anyhow!(
+                    "artifact rule \"{}\": unknown artifact \"{}\"",
+                    rule.name,
+                    rule.artifact_from.as_ref().unwrap()
+                ))
+                .located(rule.location.as_ref());
+            }
+
+            for rule in ready {
+                let name = rule.artifact.as_ref().unwrap();
+                let input = match &rule.artifact_from {
+                    Some(from) => &artifacts[from],
+                    None => &outfile,
+                };
+                let out = rule
+                    .out
+                    .as_ref()
+                    .ok_or_else(|| {
+                        anyhow!("artifact rule \"{}\" has no \"out\" extension", rule.name)
+                    })
+                    .located(rule.location.as_ref())?;
+                let artifact = outfile.with_extension(out);
+                if artifact == outfile {
+                    return Err(anyhow!(
+                        "artifact rule \"{}\": output \"{artifact}\" would overwrite the app",
+                        rule.name
+                    ))
+                    .located(rule.location.as_ref());
+                }
+                if artifacts.values().contains(&artifact) {
+                    return Err(anyhow!(
+                        "artifact rule \"{}\": output \"{artifact}\" is not unique",
+                        rule.name
+                    ))
+                    .located(rule.location.as_ref());
+                }
+
+                let ninja_rule = rule
+                    .to_ninja(&global_env_flattened)
+                    .with_context(|| format!("rendering artifact rule \"{}\"", rule.name))?;
+                let build = NinjaBuildBuilder::from_rule(&ninja_rule)
+                    .input(input.as_path())
+                    .out(artifact.as_path())
+                    .build()
+                    .unwrap();
+
+                ninja_entries.insert(format!("{ninja_rule}"));
+                ninja_entries.insert(format!("{build}"));
+                artifacts.insert(name.clone(), artifact);
+            }
+            pending = waiting;
+        }
+    }
+
     // post link
     let outfile =
         {
@@ -1023,6 +1455,13 @@ fn configure_build(
 
     // collect tasks
     global_env_flattened.insert(&out_str, Cow::from(outfile.to_string()));
+    let artifact_vars = artifacts
+        .iter()
+        .map(|(name, artifact)| (format!("artifact::{name}"), artifact.to_string()))
+        .collect_vec();
+    for (var, artifact) in &artifact_vars {
+        global_env_flattened.insert(var, Cow::from(artifact));
+    }
     let tasks = build
         .build_context
         .collect_tasks(contexts, &global_env_flattened, &modules)?;
@@ -1033,6 +1472,7 @@ fn configure_build(
             builder: builder.name.clone(),
             tasks,
             out: outfile,
+            artifacts,
             module_info,
         },
         ninja_entries,
@@ -1063,6 +1503,17 @@ impl Selector {
             true
         }
     }
+
+    /// Like [`Selector::selects`], but also selects variants of a builder by
+    /// its name or some of their values (see [`Context::variant_selected`]).
+    pub fn selects_builder(&self, builder: &str) -> bool {
+        if let Selector::Some(set) = self {
+            set.iter()
+                .any(|selector| selector == builder || Context::variant_selected(selector, builder))
+        } else {
+            true
+        }
+    }
 }
 
 impl fmt::Display for Selector {
@@ -1112,6 +1563,7 @@ pub struct GenerateResult {
     cli_env_hash: u64,
     treestate: FileTreeState,
     partitioner: Option<String>,
+    offline: bool,
 }
 
 impl GenerateResult {
@@ -1131,6 +1583,7 @@ impl GenerateResult {
             build_infos,
             treestate,
             partitioner: generator.partitioner,
+            offline: offline::enabled(),
         }
     }
 
@@ -1204,6 +1657,10 @@ impl TryFrom<&Generator> for GenerateResult {
         if res.cli_env_hash != generator.cli_env.as_ref().map_or(0, utils::calculate_hash) {
             return Err(anyhow!("laze: CLI env doesn't match"));
         }
+        if res.offline != offline::enabled() {
+            // an online configure run didn't check that everything is available offline
+            return Err(anyhow!("laze: offline mode doesn't match"));
+        }
         if res.treestate.has_changed() {
             return Err(anyhow!("laze: build files have changed"));
         }
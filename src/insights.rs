@@ -29,6 +29,8 @@ impl Insights {
 #[derive(Deserialize, Serialize, Debug)]
 pub struct InsightBuildInfo {
     pub outfile: Utf8PathBuf,
+    #[serde(skip_serializing_if = "IndexMap::is_empty", default)]
+    pub artifacts: IndexMap<String, Utf8PathBuf>,
     pub modules: IndexMap<String, ModuleInfo>,
 }
 
@@ -36,6 +38,7 @@ impl From<&BuildInfo> for InsightBuildInfo {
     fn from(build_info: &BuildInfo) -> Self {
         Self {
             outfile: build_info.out.clone(),
+            artifacts: build_info.artifacts.clone(),
             modules: build_info.module_info.as_ref().unwrap().clone(),
         }
     }
@@ -1,26 +1,593 @@
 //! Inspect Laze project files, without any build output
 
+use std::borrow::Cow;
+use std::collections::BTreeMap;
 use std::io::Write;
 
-use anyhow::Result;
-use camino::Utf8PathBuf;
+use anyhow::{anyhow, Context as _, Result};
+use camino::{Utf8Path, Utf8PathBuf};
+use im::Vector;
+use indexmap::IndexSet;
+use itertools::Itertools;
 use ptree::{write_tree, TreeBuilder};
+use serde::Serialize;
 
-use crate::{data::load, Context, ContextBag};
+use crate::{
+    build::{Build, Explanation, ResolverResult},
+    data::load,
+    generate::{check_build_allowed, global_env, laze_env},
+    nested_env::{EnvKey, EnvMap},
+    Context, ContextBag, Dependency, Env, Module,
+};
+
+mod graph;
+use graph::Graph;
+pub(crate) use graph::GraphFormat;
 
 pub(crate) struct BuildInspector {
     contexts: ContextBag,
+    project_root: Utf8PathBuf,
+    build_dir: Utf8PathBuf,
+}
+
+/// Extra build settings (`--select`, `--disable`, ...), as given on the
+/// command line
+#[derive(Default)]
+pub(crate) struct BuildSettings {
+    pub select: Option<Vec<Dependency<String>>>,
+    pub disable: Option<Vec<String>>,
+    pub require: Option<Vec<String>>,
+    pub define: Option<Env>,
+}
+
+/// A variable as printed by `laze inspect env`
+#[derive(Serialize)]
+pub(crate) struct EnvVar<'a> {
+    /// the value after flattening (applying `var_options`)
+    pub value: Cow<'a, str>,
+    /// the unflattened value, if this is a list
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub list: Option<&'a Vector<String>>,
+    /// where the value (or each list element) was set, if requested
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub origins: Option<Vec<&'a str>>,
+}
+
+impl EnvVar<'_> {
+    pub(crate) fn write_vars_text<W: Write>(
+        mut w: W,
+        vars: &BTreeMap<&str, EnvVar<'_>>,
+    ) -> Result<()> {
+        for (name, var) in vars {
+            writeln!(w, "{name} = {}", var.value)?;
+            match (var.list, &var.origins) {
+                (Some(list), Some(origins)) => {
+                    for (value, origin) in list.iter().zip(origins) {
+                        writeln!(w, "  - {value:?} from {origin}")?;
+                    }
+                }
+                (Some(list), None) => {
+                    writeln!(w, "  list: {:?}", list.iter().collect::<Vec<_>>())?;
+                }
+                (None, Some(origins)) => {
+                    for origin in origins {
+                        writeln!(w, "  from {origin}")?;
+                    }
+                }
+                (None, None) => (),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Summary of a module definition, as printed by `laze inspect modules`
+#[derive(Serialize)]
+pub(crate) struct ModuleSummary<'a> {
+    pub name: &'a str,
+    pub context: &'a str,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub is_app: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub help: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub defined_in: Option<&'a Utf8Path>,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    pub provides: &'a [String],
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    pub conflicts: &'a [String],
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    pub requires: &'a [String],
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub selects: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub uses: Vec<String>,
+}
+
+impl<'a> From<&'a Module> for ModuleSummary<'a> {
+    fn from(module: &'a Module) -> Self {
+        Self {
+            name: &module.name,
+            context: &module.context_name,
+            is_app: module.is_binary,
+            help: module.help.as_deref(),
+            defined_in: module.defined_in.as_deref(),
+            provides: module.provides.as_deref().unwrap_or_default(),
+            conflicts: module.conflicts.as_deref().unwrap_or_default(),
+            requires: module.requires.as_deref().unwrap_or_default(),
+            selects: module.selects.iter().map(|dep| dep.to_string()).collect(),
+            uses: module.imports.iter().map(|dep| dep.to_string()).collect(),
+        }
+    }
+}
+
+impl ModuleSummary<'_> {
+    pub(crate) fn write_text<W: Write>(&self, mut w: W) -> Result<()> {
+        let kind = if self.is_app { "app" } else { "module" };
+        writeln!(w, "{kind} {} (context: {})", self.name, self.context)?;
+        if let Some(help) = self.help {
+            writeln!(w, "  help: {}", help.trim_end())?;
+        }
+        if let Some(defined_in) = self.defined_in {
+            writeln!(w, "  defined in: {defined_in}")?;
+        }
+        for (field, list) in [
+            ("provides", self.provides),
+            ("conflicts", self.conflicts),
+            ("requires", self.requires),
+            ("selects", &self.selects[..]),
+            ("uses", &self.uses[..]),
+        ] {
+            if !list.is_empty() {
+                writeln!(w, "  {field}: {}", list.join(", "))?;
+            }
+        }
+        Ok(())
+    }
 }
 
 impl BuildInspector {
-    pub(crate) fn from_project(project_file: Utf8PathBuf, build_dir: Utf8PathBuf) -> Result<Self> {
+    pub(crate) fn from_project(
+        project_root: Utf8PathBuf,
+        project_file: Utf8PathBuf,
+        build_dir: Utf8PathBuf,
+    ) -> Result<Self> {
         let (contexts, _, _) = load(&project_file, &build_dir)?;
-        Ok(Self { contexts })
+        Ok(Self {
+            contexts,
+            project_root,
+            build_dir,
+        })
     }
     pub(crate) fn inspect_builders(&self) -> Vec<&Context> {
         self.contexts.builders_vec()
     }
 
+    /// Returns all modules (including apps, excluding context modules),
+    /// optionally limited to those defined in context `context`.
+    pub(crate) fn inspect_modules(&self, context: Option<&String>) -> Result<Vec<&Module>> {
+        if let Some(context) = context {
+            if self.contexts.get_by_name(context).is_none() {
+                return Err(anyhow!("unknown context \"{context}\""));
+            }
+        }
+
+        Ok(self
+            .contexts
+            .modules()
+            .map(|(_, module)| module)
+            .filter(|module| !module.is_context_module())
+            .filter(|module| context.is_none_or(|context| &module.context_name == context))
+            .collect())
+    }
+
+    /// Looks up builder `builder` and app `app`.
+    pub(crate) fn get_builder_and_app(
+        &self,
+        builder: &str,
+        app: &str,
+    ) -> Result<(&Context, &Module)> {
+        let builder = match self.contexts.get_by_name(&builder.to_string()) {
+            Some(context) if context.is_builder => context,
+            Some(_) => return Err(anyhow!("context \"{builder}\" is not a builder")),
+            None => {
+                return Err(match self.contexts.closest_builder_within(builder, 5) {
+                    Some(closest) => anyhow!(
+                        "unknown builder \"{builder}\", did you mean \"{}\"?",
+                        closest.name
+                    ),
+                    None => anyhow!("unknown builder \"{builder}\""),
+                })
+            }
+        };
+
+        let app = self
+            .contexts
+            .modules()
+            .map(|(_, module)| module)
+            .find(|module| module.is_binary && module.name == app)
+            .ok_or_else(|| anyhow!("unknown app \"{app}\""))?;
+
+        Ok((builder, app))
+    }
+
+    /// Sets up the build of `app` for `builder`, along with the disabled and
+    /// required modules to resolve it with (including those given in
+    /// `settings`).
+    ///
+    /// Returns the reason if `app` is not built for `builder`.
+    fn setup_build<'b>(
+        &'b self,
+        builder: &'b Context,
+        app: &'b Module,
+        settings: &BuildSettings,
+    ) -> Result<(Build<'b>, IndexSet<String>, IndexSet<String>), String> {
+        if let Some(reason) = check_build_allowed(app, &self.contexts, builder) {
+            return Err(format!(
+                "app \"{}\" is not built for builder \"{}\": {reason}",
+                app.name, builder.name
+            ));
+        }
+
+        let build = Build::new(app, builder, &self.contexts, settings.select.as_ref());
+        let mut disabled_modules = build.build_context.collect_disabled_modules(&self.contexts);
+        if let Some(disable) = &settings.disable {
+            disabled_modules.extend(disable.iter().cloned());
+        }
+        let required_modules = settings.require.iter().flatten().cloned().collect();
+
+        Ok((build, disabled_modules, required_modules))
+    }
+
+    /// Resolves the build of `app` for `builder` and passes it to `f`.
+    fn with_resolved_build<T>(
+        &self,
+        builder: &str,
+        app: &str,
+        settings: &BuildSettings,
+        f: impl FnOnce(&Build, &ResolverResult) -> Result<T>,
+    ) -> Result<T> {
+        let (builder, app) = self.get_builder_and_app(builder, app)?;
+        let (build, disabled_modules, required_modules) = self
+            .setup_build(builder, app, settings)
+            .map_err(|reason| anyhow!(reason))?;
+        let resolved = build
+            .resolve_selects(disabled_modules, required_modules)
+            .with_context(|| {
+                format!(
+                    "app \"{}\" cannot be built for builder \"{}\"",
+                    app.name, builder.name
+                )
+            })?;
+
+        f(&build, &resolved)
+    }
+
+    /// Writes the variables of the build of `app` for `builder` to `w`.
+    ///
+    /// Without `module_name`, this is the global environment. Otherwise, it
+    /// is the environment of module `module_name` within that build.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn write_env<W: Write>(
+        &self,
+        mut w: W,
+        builder: &str,
+        app: &str,
+        module_name: Option<&String>,
+        settings: &BuildSettings,
+        json: bool,
+        origin: bool,
+    ) -> Result<()> {
+        self.with_resolved_build(builder, app, settings, |build, resolved| {
+            let laze_env = laze_env(&self.project_root, &self.build_dir);
+            let global_env = global_env(
+                &laze_env,
+                build,
+                resolved,
+                &self.contexts,
+                settings.define.as_ref(),
+            );
+
+            let env = if let Some(module_name) = module_name {
+                let module = resolved.modules.get(module_name).ok_or_else(|| {
+                    anyhow!(
+                        "module \"{module_name}\" is not part of this build (try `laze inspect why`)"
+                    )
+                })?;
+                module.build_env(&global_env, resolved).0
+            } else {
+                global_env
+            };
+
+            let flattened = env
+                .flatten_with_opts_option(build.builder().var_options.as_ref())
+                .context("flattening environment")?;
+
+            let vars = Self::env_vars(&env, flattened, origin);
+
+            if json {
+                serde_json::to_writer_pretty(&mut w, &vars)?;
+                writeln!(w)?;
+            } else {
+                EnvVar::write_vars_text(&mut w, &vars)?;
+            }
+
+            Ok(())
+        })
+    }
+
+    fn env_vars<'a>(
+        env: &'a Env,
+        flattened: EnvMap<'a>,
+        origin: bool,
+    ) -> BTreeMap<&'a str, EnvVar<'a>> {
+        flattened
+            .into_iter()
+            .map(|(name, value)| {
+                let list = match env.get(name) {
+                    Some(EnvKey::List(list)) => Some(list),
+                    _ => None,
+                };
+                // values without a recorded origin were set by laze itself
+                let origins = origin.then(|| {
+                    env.origins(name)
+                        .unwrap_or_default()
+                        .iter()
+                        .map(|origin| origin.map_or("laze", |origin| origin))
+                        .collect()
+                });
+                (
+                    name,
+                    EnvVar {
+                        value,
+                        list,
+                        origins,
+                    },
+                )
+            })
+            .collect()
+    }
+
+    /// Writes the dependency graph of the build of `app` for `builder` to `w`.
+    pub(crate) fn write_graph<W: Write>(
+        &self,
+        w: W,
+        builder: &str,
+        app: &str,
+        settings: &BuildSettings,
+        format: GraphFormat,
+    ) -> Result<()> {
+        self.with_resolved_build(builder, app, settings, |build, resolved| {
+            Graph::new(&build.builder().name, &build.binary().name, resolved).write(w, format)
+        })
+    }
+
+    /// Explains why module `module_name` is or is not part of the build of
+    /// `app` for `builder`, writing the explanation to `w`.
+    pub(crate) fn write_why<W: Write>(
+        &self,
+        mut w: W,
+        builder: &str,
+        app: &str,
+        module_name: &str,
+        settings: &BuildSettings,
+    ) -> Result<()> {
+        let (builder, app) = self.get_builder_and_app(builder, app)?;
+
+        let (build, disabled_modules, required_modules) =
+            match self.setup_build(builder, app, settings) {
+                Ok(setup) => setup,
+                Err(reason) => {
+                    writeln!(w, "{reason}")?;
+                    return Ok(());
+                }
+            };
+
+        let explanation = build.explain_selects(disabled_modules, required_modules);
+
+        if let Err(e) = &explanation.result {
+            writeln!(
+                w,
+                "app \"{}\" cannot be built for builder \"{}\":\n{e:?}\n",
+                app.name, builder.name
+            )?;
+        }
+
+        let module_name = module_name.to_string();
+        if explanation.selected_by.contains_key(&module_name) || module_name == app.name {
+            writeln!(
+                w,
+                "module \"{module_name}\" is selected for app \"{}\" on builder \"{}\":",
+                app.name, builder.name
+            )?;
+            Self::write_selection_chain(&mut w, &explanation, &module_name, "  ")?;
+            return Ok(());
+        }
+
+        // a name that is not a module, but provided by selected modules
+        if let Ok(resolved) = &explanation.result {
+            if let Some(providers) = resolved.providers.get(&module_name) {
+                writeln!(
+                    w,
+                    "\"{module_name}\" is provided for app \"{}\" on builder \"{}\" by:",
+                    app.name, builder.name
+                )?;
+                for provider in providers {
+                    writeln!(w, "  {}", describe(provider))?;
+                    Self::write_selection_chain(&mut w, &explanation, &provider.name, "    ")?;
+                }
+                return Ok(());
+            }
+        }
+
+        let reasons = self.exclusion_reasons(
+            &build,
+            &explanation,
+            &module_name,
+            settings.disable.as_ref(),
+        )?;
+
+        writeln!(
+            w,
+            "module \"{module_name}\" is not selected for app \"{}\" on builder \"{}\":",
+            app.name, builder.name
+        )?;
+
+        for reason in reasons {
+            writeln!(w, "  {reason}")?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the chain of selections that led to `module_name` being part
+    /// of a build, starting at the app.
+    fn write_selection_chain<W: Write>(
+        mut w: W,
+        explanation: &Explanation,
+        module_name: &String,
+        indent: &str,
+    ) -> Result<()> {
+        let mut chain = Vec::new();
+        let mut current = module_name;
+        while let Some(selected_by) = explanation.selected_by.get(current) {
+            chain.push((selected_by, current));
+            current = &selected_by.module.name;
+        }
+
+        for (selected_by, selected) in chain.iter().rev() {
+            let kind = if selected_by
+                .module
+                .imports
+                .iter()
+                .any(|dep| dep.get_name() == selected_by.dependency.get_name())
+            {
+                "depends on"
+            } else {
+                "selects"
+            };
+            write!(
+                w,
+                "{indent}{} {kind} \"{}\"",
+                describe(selected_by.module),
+                selected_by.dependency
+            )?;
+            if selected_by.provided {
+                write!(w, " (provided by \"{selected}\")")?;
+            }
+            writeln!(w)?;
+        }
+
+        Ok(())
+    }
+
+    fn exclusion_reasons(
+        &self,
+        build: &Build,
+        explanation: &Explanation,
+        module_name: &String,
+        disable: Option<&Vec<String>>,
+    ) -> Result<Vec<String>> {
+        let mut reasons = Vec::new();
+
+        if build
+            .build_context
+            .resolve_module(module_name, &self.contexts)
+            .is_none()
+        {
+            let defined_in = self
+                .contexts
+                .modules()
+                .filter(|(name, _)| *name == module_name)
+                .map(|(_, module)| format!("\"{}\"", module.context_name))
+                .collect::<Vec<_>>();
+            let provided = build
+                .build_context
+                .provided
+                .as_ref()
+                .is_some_and(|provided| provided.contains_key(module_name));
+            if defined_in.is_empty() && !provided {
+                return Err(anyhow!("unknown module \"{module_name}\""));
+            }
+            if !defined_in.is_empty() {
+                reasons.push(format!(
+                    "defined in context {}, which builder \"{}\" does not inherit from",
+                    defined_in.join(", "),
+                    build.builder().name
+                ));
+            }
+        }
+
+        // a module disabled up front never gets considered, so whatever the
+        // resolver reports about it would only be a consequence of that
+        if disable.is_some_and(|disable| disable.contains(module_name)) {
+            reasons.push("disabled on the command line".to_string());
+        }
+        for context in build.builder().context_iter(&self.contexts) {
+            if context
+                .disable
+                .as_ref()
+                .is_some_and(|disable| disable.contains(module_name))
+            {
+                reasons.push(format!("disabled by context \"{}\"", context.name));
+            }
+        }
+
+        // otherwise, explain the final decision: either a selected module
+        // conflicts it, or the last attempt to select it failed
+        if reasons.is_empty() {
+            if let Some(disabled_by) = explanation
+                .disabled
+                .get(module_name)
+                .filter(|disabled_by| !disabled_by.is_empty())
+            {
+                let mut disabled_by = disabled_by
+                    .iter()
+                    .sorted()
+                    .map(|name| format!("\"{name}\""));
+                reasons.push(format!("conflicted by {}", disabled_by.join(", ")));
+            } else if let Some(rejected) = explanation
+                .rejected
+                .get(module_name)
+                .and_then(|rejected| rejected.last())
+            {
+                reasons.push(rejected.clone());
+            }
+        }
+
+        if reasons.is_empty() {
+            let used_by = explanation
+                .selected_by
+                .keys()
+                .map(|name| name.as_str())
+                .chain(std::iter::once(build.binary().name.as_str()))
+                .filter_map(|name| {
+                    build
+                        .build_context
+                        .resolve_module(&name.to_string(), &self.contexts)
+                        .map(|(_, module)| module)
+                })
+                .filter(|module| {
+                    module
+                        .imports
+                        .iter()
+                        .any(|dep| &dep.get_name() == module_name)
+                })
+                .map(|module| format!("\"{}\"", module.name))
+                .collect::<Vec<_>>();
+            if used_by.is_empty() {
+                reasons.push("no module in this build selects or depends on it".to_string());
+            } else {
+                reasons.push(format!(
+                    "used by {}, but not selected (\"uses\" does not select)",
+                    used_by.join(", ")
+                ));
+            }
+        }
+
+        Ok(reasons)
+    }
+
     fn add_tree_element(&self, context: &Context, tree: &mut TreeBuilder) {
         self.contexts
             .contexts
@@ -41,3 +608,14 @@ impl BuildInspector {
         write_tree(&tree, w).map_err(|e| e.into())
     }
 }
+
+/// Describes a module as "app", "context" or "module", for explanations.
+fn describe(module: &Module) -> String {
+    if module.is_binary {
+        format!("app \"{}\"", module.name)
+    } else if let Some(context) = module.name.strip_prefix("context::") {
+        format!("context \"{context}\"")
+    } else {
+        format!("module \"{}\"", module.name)
+    }
+}
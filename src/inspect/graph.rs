@@ -0,0 +1,306 @@
+//! Dependency graph of a resolved build (`laze inspect graph`)
+
+use std::io::Write;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use indexmap::{IndexMap, IndexSet};
+use serde::Serialize;
+
+use crate::{build::ResolverResult, Dependency, Module};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum GraphFormat {
+    Dot,
+    Mermaid,
+    Json,
+}
+
+impl FromStr for GraphFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "dot" => Ok(GraphFormat::Dot),
+            "mermaid" => Ok(GraphFormat::Mermaid),
+            "json" => Ok(GraphFormat::Json),
+            _ => Err(anyhow!("unknown graph format \"{s}\"")),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum NodeKind {
+    App,
+    Context,
+    Module,
+    /// a name that is not a module, but provided by modules
+    Provided,
+}
+
+#[derive(Serialize, Debug)]
+pub(crate) struct Node<'a> {
+    pub name: &'a str,
+    pub kind: NodeKind,
+    /// every module that is not a global build dependency is built after this
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub global_build_dep: bool,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum EdgeKind {
+    /// `depends` (selects and uses)
+    Depends,
+    Selects,
+    Uses,
+    /// from a provider to the name it provides
+    Provides,
+    /// build order dependency (the dependency is built first)
+    BuildDep,
+}
+
+#[derive(Serialize, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct Edge<'a> {
+    pub from: &'a str,
+    pub to: &'a str,
+    pub kind: EdgeKind,
+    /// whether the dependency was optional (`?name`)
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub optional: bool,
+}
+
+/// The modules of a resolved build and their relationships
+#[derive(Serialize, Debug)]
+pub(crate) struct Graph<'a> {
+    pub builder: &'a str,
+    pub app: &'a str,
+    pub nodes: Vec<Node<'a>>,
+    pub edges: Vec<Edge<'a>>,
+}
+
+impl<'a> Graph<'a> {
+    pub(crate) fn new(builder: &'a str, app: &'a str, resolved: &'a ResolverResult<'a>) -> Self {
+        let modules = &resolved.modules;
+        let providers = &resolved.providers;
+
+        // a dependency is part of the graph if it (or its condition) was
+        // satisfied in this build
+        let in_build = |name: &String| modules.contains_key(name) || providers.contains_key(name);
+        let target = |dep: &'a Dependency<String>| -> Option<(&'a String, bool)> {
+            let (name, optional) = match dep {
+                Dependency::Hard(name) => (name, false),
+                Dependency::Soft(name) => (name, true),
+                Dependency::IfThenHard(other, name) if modules.contains_key(other) => (name, false),
+                Dependency::IfThenSoft(other, name) if modules.contains_key(other) => (name, true),
+                _ => return None,
+            };
+            in_build(name).then_some((name, optional))
+        };
+
+        let mut nodes: IndexMap<&str, Node> = modules
+            .values()
+            .map(|module| {
+                let kind = if module.is_binary {
+                    NodeKind::App
+                } else if module.is_context_module() {
+                    NodeKind::Context
+                } else {
+                    NodeKind::Module
+                };
+                (
+                    module.name.as_str(),
+                    Node {
+                        name: &module.name,
+                        kind,
+                        global_build_dep: module.is_global_build_dep,
+                    },
+                )
+            })
+            .collect();
+
+        for name in providers.keys() {
+            nodes.entry(name.as_str()).or_insert(Node {
+                name,
+                kind: NodeKind::Provided,
+                global_build_dep: false,
+            });
+        }
+
+        let mut edges = IndexSet::new();
+        for module in modules.values() {
+            // `depends` end up in both `selects` and `imports`
+            for dep in &module.selects {
+                if let Some((name, optional)) = target(dep) {
+                    let kind = if module.imports.contains(dep) {
+                        EdgeKind::Depends
+                    } else {
+                        EdgeKind::Selects
+                    };
+                    edges.insert(Edge::new(module, name, kind, optional));
+                }
+            }
+            for dep in &module.imports {
+                if module.selects.contains(dep) {
+                    continue;
+                }
+                if let Some((name, optional)) = target(dep) {
+                    edges.insert(Edge::new(module, name, EdgeKind::Uses, optional));
+                }
+            }
+        }
+
+        for (name, providers) in providers {
+            for provider in providers {
+                edges.insert(Edge::new(provider, name, EdgeKind::Provides, false));
+            }
+        }
+
+        for module in modules.values() {
+            for dep in module.build_deps(resolved) {
+                edges.insert(Edge::new(module, &dep.name, EdgeKind::BuildDep, false));
+            }
+        }
+
+        // all modules that are not global build dependencies are built after
+        // those, as in `configure_build()`
+        let global_build_deps: Vec<&Module> = modules
+            .values()
+            .filter(|module| module.is_global_build_dep)
+            .copied()
+            .collect();
+        for module in modules.values() {
+            if module.is_global_build_dep {
+                continue;
+            }
+            for dep in &global_build_deps {
+                edges.insert(Edge::new(module, &dep.name, EdgeKind::BuildDep, false));
+            }
+        }
+
+        Graph {
+            builder,
+            app,
+            nodes: nodes.into_values().collect(),
+            edges: edges.into_iter().collect(),
+        }
+    }
+
+    pub(crate) fn write<W: Write>(&self, w: W, format: GraphFormat) -> Result<()> {
+        match format {
+            GraphFormat::Dot => self.write_dot(w),
+            GraphFormat::Mermaid => self.write_mermaid(w),
+            GraphFormat::Json => {
+                let mut w = w;
+                serde_json::to_writer_pretty(&mut w, self)?;
+                writeln!(w)?;
+                Ok(())
+            }
+        }
+    }
+
+    fn write_dot<W: Write>(&self, mut w: W) -> Result<()> {
+        writeln!(
+            w,
+            "digraph \"{} ({})\" {{",
+            dot_escape(self.app),
+            dot_escape(self.builder)
+        )?;
+        writeln!(w, "  rankdir=LR;")?;
+        for node in &self.nodes {
+            let mut attrs = vec![match node.kind {
+                NodeKind::App => "shape=box, style=bold",
+                NodeKind::Context => "shape=box, style=rounded",
+                NodeKind::Module => "shape=ellipse",
+                NodeKind::Provided => "shape=diamond, style=dashed",
+            }];
+            if node.global_build_dep {
+                attrs.push("peripheries=2");
+            }
+            writeln!(w, "  \"{}\" [{}];", dot_escape(node.name), attrs.join(", "))?;
+        }
+        for edge in &self.edges {
+            let mut attrs = vec![match edge.kind {
+                EdgeKind::Depends => "style=solid",
+                EdgeKind::Selects => "style=dashed",
+                EdgeKind::Uses => "style=dotted",
+                EdgeKind::Provides => "style=dashed, arrowhead=empty, color=gray",
+                EdgeKind::BuildDep => "style=bold, color=red",
+            }];
+            if edge.optional {
+                attrs.push("arrowtail=odot, dir=both");
+            }
+            writeln!(
+                w,
+                "  \"{}\" -> \"{}\" [{}];",
+                dot_escape(edge.from),
+                dot_escape(edge.to),
+                attrs.join(", ")
+            )?;
+        }
+        writeln!(w, "}}")?;
+        Ok(())
+    }
+
+    fn write_mermaid<W: Write>(&self, mut w: W) -> Result<()> {
+        // mermaid node ids cannot contain most special characters, so nodes
+        // get numbered ids and their names as labels
+        let ids: IndexMap<&str, usize> = self
+            .nodes
+            .iter()
+            .enumerate()
+            .map(|(i, node)| (node.name, i))
+            .collect();
+
+        writeln!(w, "flowchart LR")?;
+        for (i, node) in self.nodes.iter().enumerate() {
+            let name = node.name.replace('"', "#quot;");
+            let shape = match node.kind {
+                NodeKind::App => format!("[[\"{name}\"]]"),
+                NodeKind::Context => format!("(\"{name}\")"),
+                NodeKind::Module => format!("[\"{name}\"]"),
+                NodeKind::Provided => format!("{{{{\"{name}\"}}}}"),
+            };
+            let class = if node.global_build_dep {
+                ":::global_build_dep"
+            } else {
+                ""
+            };
+            writeln!(w, "  n{i}{shape}{class}")?;
+        }
+        for edge in &self.edges {
+            let arrow = match (edge.kind, edge.optional) {
+                (EdgeKind::Depends, false) => "-->",
+                (EdgeKind::Depends, true) => "-->|optional|",
+                (EdgeKind::Selects, false) => "-.->|selects|",
+                (EdgeKind::Selects, true) => "-.->|selects optional|",
+                (EdgeKind::Uses, false) => "-.->|uses|",
+                (EdgeKind::Uses, true) => "-.->|uses optional|",
+                (EdgeKind::Provides, _) => "--o|provides|",
+                (EdgeKind::BuildDep, _) => "==>|build dep|",
+            };
+            writeln!(w, "  n{} {arrow} n{}", ids[edge.from], ids[edge.to])?;
+        }
+        if self.nodes.iter().any(|node| node.global_build_dep) {
+            writeln!(w, "  classDef global_build_dep stroke-width:3px")?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a> Edge<'a> {
+    fn new(from: &'a Module, to: &'a str, kind: EdgeKind, optional: bool) -> Self {
+        Edge {
+            from: &from.name,
+            to,
+            kind,
+            optional,
+        }
+    }
+}
+
+/// Escapes `s` for use within a quoted DOT id.
+fn dot_escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
@@ -0,0 +1,429 @@
+//! Static checks on loaded laze files (`laze lint`)
+
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::io::Write;
+
+use anyhow::Result;
+use camino::{Utf8Path, Utf8PathBuf};
+use indexmap::{IndexMap, IndexSet};
+use serde::Serialize;
+
+use crate::model::IsAncestor;
+use crate::{Context, ContextBag, Dependency, Module, Rule, Task};
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum LintLevel {
+    Warning,
+    /// something that makes a build or task fail
+    Error,
+}
+
+impl std::fmt::Display for LintLevel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LintLevel::Warning => write!(f, "warning"),
+            LintLevel::Error => write!(f, "error"),
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct Lint {
+    pub level: LintLevel,
+    /// the file the offending module, context or task was defined in
+    pub defined_in: Option<Utf8PathBuf>,
+    pub message: String,
+}
+
+impl std::fmt::Display for Lint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(defined_in) = &self.defined_in {
+            write!(f, "{defined_in}: ")?;
+        }
+        write!(f, "{}: {}", self.level, self.message)
+    }
+}
+
+/// Runs all checks on `contexts`.
+///
+/// Modules defined below `build_dir` (i.e., imported ones) are not expected
+/// to be used by the project, so they are not reported as unreachable.
+pub fn lint(contexts: &ContextBag, build_dir: &Utf8Path) -> Vec<Lint> {
+    let linter = Linter::new(contexts);
+    let mut lints = Vec::new();
+
+    linter.unknown_dependencies(&mut lints);
+    linter.sources_without_rule(&mut lints);
+    linter.shadowed_rules(&mut lints);
+    linter.unreachable_modules(build_dir, &mut lints);
+    linter.unknown_task_modules(&mut lints);
+
+    lints
+}
+
+/// Writes `lints` followed by a summary line.
+pub fn write_text<W: Write>(mut w: W, lints: &[Lint]) -> Result<()> {
+    for lint in lints {
+        writeln!(w, "{lint}")?;
+    }
+
+    let count = |level| lints.iter().filter(|lint| lint.level == level).count();
+    let plural = |n| if n == 1 { "" } else { "s" };
+    let (errors, warnings) = (count(LintLevel::Error), count(LintLevel::Warning));
+    writeln!(
+        w,
+        "{errors} error{}, {warnings} warning{}",
+        plural(errors),
+        plural(warnings)
+    )?;
+
+    Ok(())
+}
+
+struct Linter<'a> {
+    contexts: &'a ContextBag,
+    /// all modules by name (a name may be defined in multiple contexts)
+    modules: IndexMap<&'a str, Vec<&'a Module>>,
+    /// all modules providing a name
+    providers: IndexMap<&'a str, Vec<&'a Module>>,
+}
+
+impl<'a> Linter<'a> {
+    fn new(contexts: &'a ContextBag) -> Self {
+        let mut modules: IndexMap<&str, Vec<&Module>> = IndexMap::new();
+        let mut providers: IndexMap<&str, Vec<&Module>> = IndexMap::new();
+        for (name, module) in contexts.modules() {
+            modules.entry(name).or_default().push(module);
+            for provided in module.provides.iter().flatten() {
+                providers.entry(provided).or_default().push(module);
+            }
+        }
+
+        Linter {
+            contexts,
+            modules,
+            providers,
+        }
+    }
+
+    fn is_known(&self, name: &str) -> bool {
+        self.modules.contains_key(name) || self.providers.contains_key(name)
+    }
+
+    /// Where `module` was defined. Context modules use their context's file.
+    fn defined_in(&self, module: &Module) -> Option<Utf8PathBuf> {
+        module.defined_in.clone().or_else(|| {
+            self.contexts
+                .get_by_name(&module.context_name)
+                .and_then(|context| context.defined_in.clone())
+        })
+    }
+
+    fn describe(module: &Module) -> String {
+        match module.name.strip_prefix("context::") {
+            Some(context) => format!("context \"{context}\""),
+            None if module.is_binary => format!("app \"{}\"", module.name),
+            None => format!("module \"{}\"", module.name),
+        }
+    }
+
+    /// Reports names in selects / depends / uses / conflicts that are
+    /// neither a module nor provided by one.
+    ///
+    /// Unknown hard dependencies fail every build they are part of, so they
+    /// are errors. Everything else is only suspicious.
+    fn unknown_dependencies(&self, lints: &mut Vec<Lint>) {
+        for (_, module) in self.contexts.modules() {
+            let mut report = |level, what: &str, name: &str| {
+                lints.push(Lint {
+                    level,
+                    defined_in: self.defined_in(module),
+                    message: format!(
+                        "{} {what} unknown module \"{name}\"",
+                        Self::describe(module)
+                    ),
+                })
+            };
+
+            // `depends` end up in both lists, only report those once
+            let mut seen = IndexSet::new();
+            let deps = module
+                .selects
+                .iter()
+                .map(|dep| (dep, true))
+                .chain(module.imports.iter().map(|dep| (dep, false)));
+
+            for (dep, is_select) in deps {
+                if !seen.insert(dep.to_string()) {
+                    continue;
+                }
+                let (name, condition, hard) = match dep {
+                    Dependency::Hard(name) => (name, None, true),
+                    Dependency::Soft(name) => (name, None, false),
+                    Dependency::IfThenHard(other, name) => (name, Some(other), true),
+                    Dependency::IfThenSoft(other, name) => (name, Some(other), false),
+                };
+                let what = match (is_select, module.imports.contains(dep)) {
+                    (true, true) => "depends on",
+                    (true, false) => "selects",
+                    _ => "uses",
+                };
+                if !self.is_known(name) {
+                    let level = if hard && is_select {
+                        LintLevel::Error
+                    } else {
+                        LintLevel::Warning
+                    };
+                    report(level, what, name);
+                }
+                if let Some(condition) = condition {
+                    if !self.is_known(condition) {
+                        report(LintLevel::Warning, "has a condition on", condition);
+                    }
+                }
+            }
+
+            for name in module.conflicts.iter().flatten() {
+                if !self.is_known(name) {
+                    report(LintLevel::Warning, "conflicts with", name);
+                }
+            }
+        }
+    }
+
+    /// Reports sources that no rule matches (or whose explicitly chosen rule
+    /// doesn't exist) in any builder the module can be built for.
+    fn sources_without_rule(&self, lints: &mut Vec<Lint>) {
+        // rule extensions (or names) and rule names available per context
+        let mut extensions: IndexMap<&str, (IndexSet<String>, IndexSet<String>)> = IndexMap::new();
+
+        for (_, module) in self.contexts.modules() {
+            // custom builds don't use rules
+            if module.build.is_some() {
+                continue;
+            }
+
+            let Some(context) = self.contexts.get_by_name(&module.context_name) else {
+                continue;
+            };
+
+            let (available, names) = extensions.entry(&context.name).or_insert_with(|| {
+                self.builders_below(context)
+                    .flat_map(|builder| {
+                        let mut rules = IndexMap::new();
+                        builder
+                            .collect_rules(self.contexts, &mut rules)
+                            .iter()
+                            .map(|(key, rule)| (key.clone(), rule.name.clone()))
+                            .collect::<Vec<_>>()
+                    })
+                    .unzip()
+            });
+
+            // a context without builders cannot be built for at all
+            if available.is_empty() {
+                continue;
+            }
+
+            // add the rules of the module itself and exported by its imports
+            let mut available = Cow::Borrowed(&*available);
+            let mut names = Cow::Borrowed(&*names);
+            for rule in self.module_rules(module) {
+                available.to_mut().extend(rule.keys().iter().cloned());
+                names.to_mut().insert(rule.name.clone());
+            }
+
+            let optional_sources = module.sources_optional.iter().flat_map(|map| map.values());
+            for source in module.sources.iter().chain(optional_sources.flatten()) {
+                let rule = module
+                    .source_overrides
+                    .get(source)
+                    .and_then(|source_override| source_override.rule.as_ref())
+                    .or(module.rule.as_ref());
+                if let Some(rule) = rule {
+                    if !names.contains(rule) {
+                        lints.push(Lint {
+                            level: LintLevel::Error,
+                            defined_in: self.defined_in(module),
+                            message: format!(
+                                "{}: no rule \"{rule}\" for \"{source}\" in any builder",
+                                Self::describe(module)
+                            ),
+                        });
+                    }
+                    continue;
+                }
+
+                if available
+                    .iter()
+                    .any(|input| Rule::input_matches(input, source))
+                {
+                    continue;
+                }
+
+                let message = match Utf8Path::new(source).extension() {
+                    None => format!(
+                        "{}: source file \"{source}\" has no extension",
+                        Self::describe(module)
+                    ),
+                    Some(ext) => format!(
+                        "{}: no rule for \"{source}\" (\"{ext}\") in any builder",
+                        Self::describe(module)
+                    ),
+                };
+                lints.push(Lint {
+                    level: LintLevel::Error,
+                    defined_in: self.defined_in(module),
+                    message,
+                });
+            }
+        }
+    }
+
+    /// Returns the rules `module` defines itself and those exported by the
+    /// modules it (transitively) uses.
+    fn module_rules(&self, module: &'a Module) -> Vec<&'a Rule> {
+        let mut result = Vec::new();
+        let mut seen = IndexSet::new();
+        let mut todo = vec![module];
+        while let Some(current) = todo.pop() {
+            if !seen.insert(current as *const Module) {
+                continue;
+            }
+            if std::ptr::eq(current, module) || current.export_rules {
+                result.extend(current.rules.iter().flat_map(|rules| rules.values()));
+            }
+            for dep in &current.imports {
+                let name = dep.get_name();
+                let found = self.modules.get(name.as_str()).into_iter().flatten();
+                let providers = self.providers.get(name.as_str()).into_iter().flatten();
+                todo.extend(found.chain(providers));
+            }
+        }
+        result
+    }
+
+    /// Returns all builders that are `context` or one of its children.
+    fn builders_below(&self, context: &'a Context) -> impl Iterator<Item = &'a Context> + '_ {
+        let index = context.index.unwrap();
+        self.contexts.builders().filter(move |builder| {
+            matches!(
+                self.contexts.is_ancestor(index, builder.index.unwrap(), 0),
+                IsAncestor::Yes(..)
+            )
+        })
+    }
+
+    /// Reports rules that replace a rule of a parent context.
+    fn shadowed_rules(&self, lints: &mut Vec<Lint>) {
+        for context in &self.contexts.contexts {
+            let Some(rules) = &context.rules else {
+                continue;
+            };
+            for rule in rules.values() {
+                let shadowed = context
+                    .context_iter(self.contexts)
+                    .skip(1)
+                    .find_map(|parent| {
+                        parent
+                            .rules
+                            .iter()
+                            .flat_map(|rules| rules.values())
+                            .find(|other| other.keys().iter().any(|key| rule.keys().contains(key)))
+                            .map(|other| (parent, other))
+                    });
+
+                if let Some((parent, other)) = shadowed {
+                    lints.push(Lint {
+                        level: LintLevel::Warning,
+                        defined_in: context.defined_in.clone(),
+                        message: format!(
+                            "context \"{}\": rule \"{}\" shadows rule \"{}\" of parent context \"{}\"",
+                            context.name,
+                            rule.name,
+                            other.name,
+                            parent.name
+                        ),
+                    });
+                }
+            }
+        }
+    }
+
+    /// Reports modules that no app or context selects, directly or
+    /// indirectly. Such modules can only be used through `--select`.
+    fn unreachable_modules(&self, build_dir: &Utf8Path, lints: &mut Vec<Lint>) {
+        let mut reached: IndexSet<&str> = IndexSet::new();
+        let mut queue: VecDeque<&Module> = self
+            .contexts
+            .modules()
+            .map(|(_, module)| module)
+            .filter(|module| module.is_binary || module.is_context_module())
+            .collect();
+
+        while let Some(module) = queue.pop_front() {
+            if !reached.insert(&module.name) {
+                continue;
+            }
+            for dep in &module.selects {
+                let name = dep.get_name();
+                let targets = self.modules.get(name.as_str()).into_iter().flatten();
+                let providers = self.providers.get(name.as_str()).into_iter().flatten();
+                queue.extend(targets.chain(providers));
+            }
+        }
+
+        for (name, module) in self.contexts.modules() {
+            let imported = module
+                .defined_in
+                .as_ref()
+                .is_some_and(|defined_in| defined_in.starts_with(build_dir));
+            if reached.contains(name.as_str()) || imported {
+                continue;
+            }
+            lints.push(Lint {
+                level: LintLevel::Warning,
+                defined_in: self.defined_in(module),
+                message: format!("module \"{name}\" is not selected by any app or context"),
+            });
+        }
+    }
+
+    /// Reports tasks that require modules that don't exist. Those tasks are
+    /// never available.
+    fn unknown_task_modules(&self, lints: &mut Vec<Lint>) {
+        let mut check =
+            |task_name: &str, task: &Task, owner: String, defined_in: &Option<Utf8PathBuf>| {
+                for name in task.required_modules.iter().flatten() {
+                    if !self.is_known(name) {
+                        lints.push(Lint {
+                            level: LintLevel::Error,
+                            defined_in: defined_in.clone(),
+                            message: format!(
+                                "{owner}: task \"{task_name}\" requires unknown module \"{name}\""
+                            ),
+                        });
+                    }
+                }
+            };
+
+        for context in &self.contexts.contexts {
+            for (name, task) in context.tasks.iter().flatten() {
+                check(
+                    name,
+                    task,
+                    format!("context \"{}\"", context.name),
+                    &context.defined_in,
+                );
+            }
+        }
+
+        for (_, module) in self.contexts.modules() {
+            for (name, task) in &module.tasks {
+                check(name, task, Self::describe(module), &self.defined_in(module));
+            }
+        }
+    }
+}
@@ -0,0 +1,389 @@
+//! Source locations in laze files
+//!
+//! serde_yaml doesn't tell where a deserialized value came from, so laze
+//! files are parsed a second time into a tree of positions. Contexts,
+//! modules, rules and tasks keep a [`Location`] into that tree, which is used
+//! to point errors at the offending line (see [`LocatedError`]).
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+use camino::Utf8Path;
+use yaml_rust2::parser::{Event, MarkedEventReceiver, Parser};
+use yaml_rust2::scanner::Marker;
+
+/// Position of a YAML node and its children
+#[derive(Debug)]
+pub struct Node {
+    line: usize,
+    column: usize,
+    value: Value,
+}
+
+#[derive(Debug)]
+enum Value {
+    Scalar(String),
+    Seq(Vec<Arc<Node>>),
+    Map(Vec<(String, Arc<Node>)>),
+}
+
+impl Node {
+    fn new(mark: Marker, value: Value) -> Self {
+        Node {
+            line: mark.line(),
+            column: mark.col() + 1,
+            value,
+        }
+    }
+}
+
+/// Returns the position tree of each document in `text`.
+///
+/// Returns nothing if the file cannot be parsed, serde_yaml will report
+/// proper errors for that.
+pub fn parse(text: &str) -> Vec<Arc<Node>> {
+    let mut builder = TreeBuilder::default();
+    match Parser::new_from_str(text).load(&mut builder, true) {
+        Ok(()) => builder.documents,
+        Err(_) => Vec::new(),
+    }
+}
+
+/// A sequence or mapping that is still being parsed
+struct Partial {
+    node: Node,
+    anchor: usize,
+    /// the pending key of a mapping
+    key: Option<String>,
+}
+
+#[derive(Default)]
+struct TreeBuilder {
+    documents: Vec<Arc<Node>>,
+    stack: Vec<Partial>,
+    anchors: HashMap<usize, Arc<Node>>,
+}
+
+impl TreeBuilder {
+    fn start(&mut self, mark: Marker, anchor: usize, value: Value) {
+        self.stack.push(Partial {
+            node: Node::new(mark, value),
+            anchor,
+            key: None,
+        });
+    }
+
+    fn push(&mut self, node: Arc<Node>, anchor: usize) {
+        if anchor != 0 {
+            self.anchors.insert(anchor, node.clone());
+        }
+        let Some(partial) = self.stack.last_mut() else {
+            self.documents.push(node);
+            return;
+        };
+        match (&mut partial.node.value, partial.key.take()) {
+            (Value::Seq(items), _) => items.push(node),
+            (Value::Map(entries), Some(key)) => entries.push((key, node)),
+            (Value::Map(entries), None) => {
+                // the parser marks mappings after their first key
+                if entries.is_empty() {
+                    partial.node.line = node.line;
+                    partial.node.column = node.column;
+                }
+                partial.key = Some(match &node.value {
+                    Value::Scalar(key) => key.clone(),
+                    _ => String::new(),
+                });
+            }
+            (Value::Scalar(_), _) => unreachable!(),
+        }
+    }
+}
+
+impl MarkedEventReceiver for TreeBuilder {
+    fn on_event(&mut self, event: Event, mark: Marker) {
+        match event {
+            Event::Scalar(value, _, anchor, _) => {
+                self.push(Arc::new(Node::new(mark, Value::Scalar(value))), anchor)
+            }
+            Event::SequenceStart(anchor, _) => self.start(mark, anchor, Value::Seq(Vec::new())),
+            Event::MappingStart(anchor, _) => self.start(mark, anchor, Value::Map(Vec::new())),
+            Event::SequenceEnd | Event::MappingEnd => {
+                if let Some(partial) = self.stack.pop() {
+                    self.push(Arc::new(partial.node), partial.anchor);
+                }
+            }
+            Event::Alias(anchor) => {
+                let node = self
+                    .anchors
+                    .get(&anchor)
+                    .cloned()
+                    .unwrap_or_else(|| Arc::new(Node::new(mark, Value::Scalar(String::new()))));
+                self.push(node, 0);
+            }
+            _ => (),
+        }
+    }
+}
+
+/// A position in a laze file
+///
+/// Displays as `file:line:column`, or just `file` if the position is not
+/// known.
+#[derive(Clone)]
+pub struct Location {
+    pub file: Arc<Utf8Path>,
+    /// 1-based, 0 if unknown
+    pub line: usize,
+    /// 1-based, 0 if unknown
+    pub column: usize,
+    node: Option<Arc<Node>>,
+}
+
+impl Location {
+    pub fn new(file: &Utf8Path, node: Arc<Node>) -> Self {
+        Location {
+            file: file.into(),
+            line: node.line,
+            column: node.column,
+            node: Some(node),
+        }
+    }
+
+    pub fn at(file: &Utf8Path, line: usize, column: usize) -> Self {
+        Location {
+            file: file.into(),
+            line,
+            column,
+            node: None,
+        }
+    }
+
+    pub fn file(file: &Utf8Path) -> Self {
+        Location::at(file, 0, 0)
+    }
+
+    fn child(&self, node: &Arc<Node>) -> Self {
+        Location {
+            file: self.file.clone(),
+            line: node.line,
+            column: node.column,
+            node: Some(node.clone()),
+        }
+    }
+
+    /// Returns the location of the value of `key`, if this is a mapping.
+    pub fn key(&self, key: &str) -> Option<Self> {
+        match &self.node.as_ref()?.value {
+            Value::Map(entries) => entries
+                .iter()
+                .find(|(k, _)| k == key)
+                .map(|(_, node)| self.child(node)),
+            _ => None,
+        }
+    }
+
+    /// Returns the location of entry `index`, if this is a sequence.
+    pub fn index(&self, index: usize) -> Option<Self> {
+        match &self.node.as_ref()?.value {
+            Value::Seq(items) => items.get(index).map(|node| self.child(node)),
+            _ => None,
+        }
+    }
+
+    /// Returns the location of the list entry `value` below any of `keys`,
+    /// or this location if there is none.
+    ///
+    /// Entries are matched with an optional `?` prefix and inside of
+    /// conditional (`other: [ ... ]`) maps.
+    pub fn entry(&self, keys: &[&str], value: &str) -> Self {
+        keys.iter()
+            .find_map(|key| self.key(key)?.search(value))
+            .unwrap_or_else(|| self.clone())
+    }
+
+    /// Returns the location of the first scalar `value` (with an optional
+    /// `?` prefix) anywhere below this one.
+    pub fn search(&self, value: &str) -> Option<Self> {
+        fn find<'a>(node: &'a Arc<Node>, value: &str) -> Option<&'a Arc<Node>> {
+            match &node.value {
+                Value::Scalar(scalar) => {
+                    (scalar.strip_prefix('?').unwrap_or(scalar) == value).then_some(node)
+                }
+                Value::Seq(items) => items.iter().find_map(|node| find(node, value)),
+                Value::Map(entries) => entries.iter().find_map(|(_, node)| find(node, value)),
+            }
+        }
+
+        find(self.node.as_ref()?, value).map(|node| self.child(node))
+    }
+
+    /// Renders the source line of this location, the way rustc does.
+    pub fn snippet(&self) -> String {
+        let number = self.line.to_string();
+        let gutter = " ".repeat(number.len());
+        let arrow = format!("{gutter}--> {self}");
+
+        let text = (self.line > 0)
+            .then(|| std::fs::read_to_string(&*self.file).ok())
+            .flatten();
+        let Some(line) = text
+            .as_ref()
+            .and_then(|text| text.lines().nth(self.line - 1))
+        else {
+            return arrow;
+        };
+
+        // underline scalars (with their quotes), otherwise just the start
+        let rest: String = line.chars().skip(self.column - 1).collect();
+        let width = match self.node.as_ref().map(|node| &node.value) {
+            Some(Value::Scalar(scalar)) if !scalar.is_empty() => {
+                if rest.starts_with(scalar.as_str()) {
+                    scalar.chars().count()
+                } else if rest
+                    .get(1..)
+                    .is_some_and(|rest| rest.starts_with(scalar.as_str()))
+                {
+                    scalar.chars().count() + 2
+                } else {
+                    1
+                }
+            }
+            _ => 1,
+        };
+
+        format!(
+            "{arrow}\n{gutter} |\n{number} | {line}\n{gutter} | {}{}",
+            " ".repeat(self.column - 1),
+            "^".repeat(width)
+        )
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.file)?;
+        if self.line > 0 {
+            write!(f, ":{}:{}", self.line, self.column)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Location({self})")
+    }
+}
+
+impl PartialEq for Location {
+    fn eq(&self, other: &Self) -> bool {
+        (&self.file, self.line, self.column) == (&other.file, other.line, other.column)
+    }
+}
+
+impl Eq for Location {}
+
+/// An error that happened at a [`Location`]
+///
+/// Displays like the wrapped error, `laze` prints the location below it.
+#[derive(Debug)]
+pub struct LocatedError {
+    pub location: Location,
+    error: anyhow::Error,
+}
+
+impl fmt::Display for LocatedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.error)
+    }
+}
+
+impl std::error::Error for LocatedError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.error.source()
+    }
+}
+
+/// Returns the innermost location of `error`, if any.
+pub fn find(error: &anyhow::Error) -> Option<&Location> {
+    error
+        .chain()
+        .filter_map(|error| error.downcast_ref::<LocatedError>())
+        .map(|error| &error.location)
+        .last()
+}
+
+/// Attaches `location` to `error`, unless it already has a (more specific)
+/// one.
+pub fn locate(error: anyhow::Error, location: Option<&Location>) -> anyhow::Error {
+    match location {
+        Some(location) if find(&error).is_none() => LocatedError {
+            location: location.clone(),
+            error,
+        }
+        .into(),
+        _ => error,
+    }
+}
+
+pub trait Locate<T> {
+    /// Attaches `location` to the error, see [`locate`].
+    fn located(self, location: Option<&Location>) -> anyhow::Result<T>;
+}
+
+impl<T, E: Into<anyhow::Error>> Locate<T> for Result<T, E> {
+    fn located(self, location: Option<&Location>) -> anyhow::Result<T> {
+        self.map_err(|error| locate(error.into(), location))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const YAML: &str = "\
+contexts:
+  - name: foo
+    parent: bar
+modules:
+  - name: m
+    depends:
+      - a
+      - other:
+          - \"?b\"
+---
+apps:
+  - name: app
+";
+
+    fn root(doc: usize) -> Location {
+        Location::new(Utf8Path::new("laze.yml"), parse(YAML)[doc].clone())
+    }
+
+    #[test]
+    fn test_lookup() {
+        let context = root(0).key("contexts").unwrap().index(0).unwrap();
+        assert_eq!(context.to_string(), "laze.yml:2:5");
+        assert_eq!(context.key("parent").unwrap().to_string(), "laze.yml:3:13");
+
+        let module = root(0).key("modules").unwrap().index(0).unwrap();
+        assert_eq!(module.entry(&["depends"], "a").to_string(), "laze.yml:7:9");
+        assert_eq!(module.entry(&["depends"], "b").to_string(), "laze.yml:9:13");
+        assert_eq!(module.entry(&["selects"], "a"), module);
+        assert_eq!(module.search("b").unwrap().to_string(), "laze.yml:9:13");
+
+        let app = root(1).key("apps").unwrap().index(0).unwrap();
+        assert_eq!(app.to_string(), "laze.yml:12:5");
+    }
+
+    #[test]
+    fn test_locate_keeps_inner() {
+        let file = Utf8Path::new("laze.yml");
+        let inner = locate(anyhow::anyhow!("inner"), Some(&Location::at(file, 2, 3)));
+        let outer = locate(inner.context("outer"), Some(&Location::at(file, 1, 1)));
+        assert_eq!(find(&outer).unwrap().to_string(), "laze.yml:2:3");
+        assert_eq!(format!("{outer:#}"), "outer: inner");
+    }
+}
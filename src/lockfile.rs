@@ -0,0 +1,292 @@
+//! This module deals with `laze.lock`, which pins git sources to commits.
+//!
+//! Git imports and module downloads that follow a branch, a tag or the default
+//! branch are resolved to a commit when they are first used. The commit is
+//! recorded in `laze.lock` (next to the project file), and later loads use
+//! exactly that commit, until `laze update` refreshes the entry.
+//!
+//! Imports are resolved while loading the project. Module downloads are only
+//! resolved once a build uses the module (see [`crate::download::Resolver`]).
+
+use std::sync::OnceLock;
+
+use anyhow::{anyhow, Context as _, Result};
+use camino::{Utf8Path, Utf8PathBuf};
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use crate::download::Git;
+
+/// How `laze.lock` is used while loading a project.
+#[derive(Debug, Default)]
+pub enum Mode {
+    /// Use locked commits, add entries for new imports, drop unused ones.
+    #[default]
+    Default,
+    /// Fail if `laze.lock` would need to change.
+    Locked,
+    /// Re-resolve the imports with these names (all imports if empty).
+    Update(Vec<String>),
+}
+
+static MODE: OnceLock<Mode> = OnceLock::new();
+
+pub fn set_mode(mode: Mode) {
+    MODE.set(mode).expect("lockfile mode set only once");
+}
+
+fn mode() -> &'static Mode {
+    MODE.get_or_init(Mode::default)
+}
+
+/// Returns the name `laze update` knows a git url by (its last path
+/// component, without `.git`).
+fn url_name(url: &str) -> &str {
+    let last = url.trim_end_matches('/').rsplit('/').next().unwrap();
+    last.strip_suffix(".git").unwrap_or(last)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LockEntry {
+    url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    branch: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tag: Option<String>,
+    commit: String,
+}
+
+impl LockEntry {
+    /// Returns the name `laze update` knows this entry by (the last path
+    /// component of the url, without `.git`).
+    fn name(&self) -> &str {
+        url_name(&self.url)
+    }
+
+    /// Returns the name and the followed branch or tag, for log messages.
+    fn describe(&self) -> String {
+        match (&self.branch, &self.tag) {
+            (Some(branch), _) => format!("{} (branch {branch})", self.name()),
+            (_, Some(tag)) => format!("{} (tag {tag})", self.name()),
+            _ => self.name().to_string(),
+        }
+    }
+
+    fn is_named(&self, name: &str) -> bool {
+        self.name() == name || self.url == name
+    }
+
+    /// Returns whether `laze update` re-resolves this entry.
+    fn is_updated(&self) -> bool {
+        match mode() {
+            Mode::Update(names) => names.is_empty() || names.iter().any(|name| self.is_named(name)),
+            _ => false,
+        }
+    }
+
+    fn is_for(&self, git: &Git) -> bool {
+        match git {
+            Git::Branch { url, branch } => self.url == *url && self.branch.as_ref() == Some(branch),
+            Git::Tag { url, tag } => self.url == *url && self.tag.as_ref() == Some(tag),
+            Git::Default { url } => self.url == *url && self.branch.is_none() && self.tag.is_none(),
+            Git::Commit { .. } => false,
+        }
+    }
+
+    fn new(git: &Git, commit: String) -> Option<Self> {
+        let (url, branch, tag) = match git {
+            Git::Branch { url, branch } => (url, Some(branch.clone()), None),
+            Git::Tag { url, tag } => (url, None, Some(tag.clone())),
+            Git::Default { url } => (url, None, None),
+            Git::Commit { .. } => return None,
+        };
+        Some(Self {
+            url: url.clone(),
+            branch,
+            tag,
+            commit,
+        })
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LockfileData {
+    imports: Vec<LockEntry>,
+}
+
+#[derive(Debug)]
+pub struct Lockfile {
+    path: Utf8PathBuf,
+    locked: Vec<LockEntry>,
+    resolved: Vec<LockEntry>,
+    /// urls of module downloads, which get resolved later
+    declared: Vec<String>,
+}
+
+impl Lockfile {
+    /// Reads `laze.lock` next to `project_file`, if it exists.
+    pub fn load(project_file: &Utf8Path) -> Result<Self> {
+        let path = project_file.with_file_name("laze.lock");
+        let mut locked = if path.exists() {
+            let contents =
+                std::fs::read_to_string(&path).with_context(|| format!("reading {path}"))?;
+            serde_yaml::from_str::<LockfileData>(&contents)
+                .with_context(|| format!("parsing {path}"))?
+                .imports
+        } else {
+            Vec::new()
+        };
+        locked.sort();
+
+        Ok(Self {
+            path,
+            locked,
+            resolved: Vec::new(),
+            declared: Vec::new(),
+        })
+    }
+
+    pub fn path(&self) -> &Utf8Path {
+        &self.path
+    }
+
+    /// Returns the commit `git` is locked to, or `None` if it should be
+    /// resolved (again).
+    pub fn commit(&self, git: &Git) -> Result<Option<&str>> {
+        if matches!(git, Git::Commit { .. }) {
+            return Ok(None);
+        }
+
+        let entry = self.locked.iter().find(|entry| entry.is_for(git));
+        match (mode(), entry) {
+            (_, Some(entry)) if entry.is_updated() => Ok(None),
+            (Mode::Update(names), None) if names.is_empty() => Ok(None),
+            (_, Some(entry)) => Ok(Some(&entry.commit)),
+            (Mode::Locked, None) => Err(anyhow!(
+                "{} has no entry for git import \"{}\" (run `laze update`)",
+                self.path,
+                git.url()
+            )),
+            (_, None) => Ok(None),
+        }
+    }
+
+    /// Records the commit `git` was resolved to.
+    pub fn record(&mut self, git: &Git, commit: &str) {
+        if let Some(entry) = LockEntry::new(git, commit.to_string()) {
+            if !self.resolved.contains(&entry) {
+                self.resolved.push(entry);
+            }
+        }
+    }
+
+    /// Keeps the entry of a module download that is only resolved once a build
+    /// uses it. With `laze update`, the entry is dropped instead, so the next
+    /// build resolves it again.
+    pub fn declare(&mut self, git: &Git) {
+        if matches!(git, Git::Commit { .. }) {
+            return;
+        }
+        self.declared.push(git.url().clone());
+
+        if let Some(entry) = self.locked.iter().find(|entry| entry.is_for(git)) {
+            if !entry.is_updated() && !self.resolved.contains(entry) {
+                self.resolved.push(entry.clone());
+            }
+        }
+    }
+
+    /// Keeps all entries that were not resolved (again).
+    ///
+    /// This is used when only some of the module downloads were resolved.
+    pub fn keep_unresolved(&mut self) {
+        for entry in &self.locked {
+            if !self.resolved.iter().any(|resolved| {
+                resolved.url == entry.url
+                    && resolved.branch == entry.branch
+                    && resolved.tag == entry.tag
+            }) {
+                self.resolved.push(entry.clone());
+            }
+        }
+    }
+
+    /// Writes `laze.lock` if any entry changed, returns whether it did.
+    pub fn store(mut self) -> Result<bool> {
+        self.resolved.sort();
+
+        if let Mode::Update(names) = mode() {
+            for name in names {
+                if !self.resolved.iter().any(|entry| entry.is_named(name))
+                    && !self
+                        .declared
+                        .iter()
+                        .any(|url| url == name || url_name(url) == name)
+                {
+                    return Err(anyhow!("no git import named \"{name}\""));
+                }
+            }
+        }
+
+        if self.resolved == self.locked {
+            return Ok(false);
+        }
+
+        if let Mode::Locked = mode() {
+            return Err(anyhow!("{} is out of date (run `laze update`)", self.path));
+        }
+
+        if crate::cli::completing() || (self.resolved.is_empty() && !self.path.exists()) {
+            return Ok(false);
+        }
+
+        for entry in &self.resolved {
+            let old = self.locked.iter().find(|old| {
+                old.url == entry.url && old.branch == entry.branch && old.tag == entry.tag
+            });
+            match old {
+                Some(old) if old.commit != entry.commit => info!(
+                    "laze: updated {} {} -> {}",
+                    entry.describe(),
+                    old.commit,
+                    entry.commit
+                ),
+                Some(_) => (),
+                None => info!("laze: locked {} to {}", entry.describe(), entry.commit),
+            }
+        }
+
+        let data = LockfileData {
+            imports: self.resolved,
+        };
+        let contents = format!(
+            "# This file is generated by laze. Use `laze update` to refresh it.\n{}",
+            serde_yaml::to_string(&data)?
+        );
+        std::fs::write(&self.path, contents).with_context(|| format!("writing {}", self.path))?;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_entry_name() {
+        let git = Git::Branch {
+            url: "https://github.com/kaspar030/laze-modules.git".into(),
+            branch: "main".into(),
+        };
+        let entry = LockEntry::new(&git, "abc".into()).unwrap();
+        assert_eq!(entry.name(), "laze-modules");
+        assert!(entry.is_named("laze-modules"));
+        assert!(entry.is_named("https://github.com/kaspar030/laze-modules.git"));
+        assert!(entry.is_for(&git));
+        assert!(!entry.is_for(&Git::Default {
+            url: "https://github.com/kaspar030/laze-modules.git".into()
+        }));
+    }
+}
@@ -15,7 +15,9 @@ use signal_hook::{consts::SIGINT, flag::register_conditional_shutdown};
 #[global_allocator]
 static GLOBAL: mimalloc::MiMalloc = mimalloc::MiMalloc;
 
+mod archive;
 mod build;
+mod check;
 mod cli;
 mod data;
 mod download;
@@ -23,10 +25,14 @@ mod generate;
 mod insights;
 mod inspect;
 mod jobserver;
+mod lint;
+mod location;
+mod lockfile;
 mod model;
 mod nested_env;
 mod new;
 mod ninja;
+mod offline;
 mod serde_bool_helpers;
 mod subst_ext;
 mod task_runner;
@@ -81,15 +87,25 @@ fn ninja_run(
     let ninja_cmd = ninja_cmd.build().unwrap();
     let ninja_binary = ninja_cmd.binary;
 
+    // `laze git-clone` and `laze download-archive` run by ninja must not fetch
+    // either
+    let cmd = || {
+        let mut cmd = ninja_cmd.cmd();
+        if offline::enabled() {
+            cmd.env("LAZE_OFFLINE", "true");
+        }
+        cmd
+    };
+
     let ninja_exit = if jobs.is_some() {
         // we force some `-jN`
-        ninja_cmd.cmd().status()
+        cmd().status()
     } else if let Some(jobserver) = JOBSERVER.get() {
         // we use our own jobserver
-        jobserver.configure_make_and_run_with_fifo(&mut ninja_cmd.cmd(), |cmd| cmd.status())
+        jobserver.configure_make_and_run_with_fifo(&mut cmd(), |cmd| cmd.status())
     } else {
         // our jobserver is not available (e.g., on `laze clean`)
-        ninja_cmd.cmd().status()
+        cmd().status()
     }
     .with_context(|| format!("launching ninja binary \"{}\"", ninja_binary))?;
 
@@ -106,7 +122,10 @@ fn main() {
     let result = try_main();
     match result {
         Err(e) => {
-            if let Some(expr_err) = e.downcast_ref::<evalexpr::EvalexprError>() {
+            let expr_err = e
+                .chain()
+                .find_map(|e| e.downcast_ref::<evalexpr::EvalexprError>());
+            if let Some(expr_err) = expr_err {
                 // make expression errors more readable.
                 // TODO: factor out
                 error!("laze: expression error: {expr_err}");
@@ -123,6 +142,9 @@ fn main() {
             } else {
                 error!("laze: error: {e:#}");
             }
+            if let Some(location) = location::find(&e) {
+                error!("{}", location.snippet());
+            }
             std::process::exit(1);
         }
         Ok(code) => std::process::exit(code),
@@ -144,8 +166,10 @@ fn try_main() -> Result<i32> {
     let mut env_log_builder = env_logger::Builder::from_env(env);
     let log_builder = env_log_builder.format(|buf, record| writeln!(buf, "{}", record.args()));
 
-    let quiet = matches.get_count("quiet");
-    let verbose = matches.get_count("verbose");
+    // `git-clone` has its own (boolean) `--quiet` and `--verbose` flags
+    let count = |id| matches.try_get_one::<u8>(id).ok().flatten().copied();
+    let quiet = count("quiet").unwrap_or_default();
+    let verbose = count("verbose").unwrap_or_default();
     match (verbose, quiet) {
         (1, ..) => log_builder.filter_level(LevelFilter::Debug),
         (2.., ..) => log_builder.filter_level(LevelFilter::max()),
@@ -160,16 +184,26 @@ fn try_main() -> Result<i32> {
     ));
 
     GIT_CACHE
-        .set(GitCache::new(git_cache_dir)?)
+        .set(GitCache::new(git_cache_dir.clone())?)
         .ok()
         .expect("creating git cache directory.");
 
+    archive::set_cache_dir(Utf8PathBuf::from(&shellexpand::tilde(
+        matches.get_one::<Utf8PathBuf>("archive_cache_dir").unwrap(),
+    )));
+
+    if matches.get_flag("offline") {
+        offline::enable(git_cache_dir);
+    }
+
     // handle project independent subcommands here
     match matches.subcommand() {
         Some(("new", matches)) => cmd_new(matches),
+        Some(("schema", _)) => cmd_schema(),
         Some(("completion", matches)) => cmd_completion(matches),
         Some(("manpages", matches)) => cmd_manpages(matches),
         Some(("git-clone", matches)) => cmd_gitclone(matches),
+        Some(("download-archive", matches)) => cmd_download_archive(matches),
         _ => try_main_build(matches),
     }
 }
@@ -194,6 +228,21 @@ fn try_main_build(matches: clap::ArgMatches) -> Result<i32> {
     );
 
     let global = matches.get_flag("global");
+
+    lockfile::set_mode(match matches.subcommand() {
+        Some(("update", _)) if matches.get_flag("locked") => {
+            return Err(anyhow!("`laze update` cannot be used with `--locked`"));
+        }
+        Some(("update", matches)) => lockfile::Mode::Update(
+            matches
+                .get_many::<String>("name")
+                .map(|names| names.cloned().collect())
+                .unwrap_or_default(),
+        ),
+        _ if matches.get_flag("locked") => lockfile::Mode::Locked,
+        _ => lockfile::Mode::Default,
+    });
+
     env::set_current_dir(&project_root).context(format!("cannot change to \"{project_root}\""))?;
 
     // If there's a parent jobserver, get it now. Needs to be done early.
@@ -203,8 +252,11 @@ fn try_main_build(matches: clap::ArgMatches) -> Result<i32> {
         Some(("build", matches)) => {
             cmd_build(matches, global, project_root, project_file, start_relpath)
         }
-        Some(("inspect", matches)) => cmd_inspect(matches, project_file),
+        Some(("inspect", matches)) => cmd_inspect(matches, project_root, project_file),
+        Some(("check", matches)) => cmd_check(matches, project_root, project_file),
+        Some(("lint", matches)) => cmd_lint(matches, project_file),
         Some(("clean", matches)) => cmd_clean(matches, global, start_relpath),
+        Some(("update", matches)) => cmd_update(matches, project_file),
         _ => Ok(0),
     }
 }
@@ -230,6 +282,12 @@ fn cmd_completion(matches: &clap::ArgMatches) -> Result<i32> {
     Ok(0)
 }
 
+fn cmd_schema() -> Result<i32> {
+    serde_json::to_writer_pretty(std::io::stdout(), &data::schema())?;
+    println!();
+    Ok(0)
+}
+
 fn cmd_manpages(matches: &clap::ArgMatches) -> Result<i32> {
     fn create_manpage(cmd: clap::Command, outfile: &Utf8Path) -> Result<(), Error> {
         let man = clap_mangen::Man::new(cmd);
@@ -266,6 +324,21 @@ fn cmd_gitclone(matches: &clap::ArgMatches) -> Result<i32> {
         .get_many::<String>("sparse-add")
         .map(|v| v.into_iter().cloned().collect::<Vec<String>>());
 
+    if offline::enabled() {
+        let source = download::Source::Git(match wanted_commit {
+            Some(commit) => download::Git::Commit {
+                url: repository.clone(),
+                commit: commit.clone(),
+            },
+            None => download::Git::Default {
+                url: repository.clone(),
+            },
+        });
+        if !offline::available(&source) {
+            return Err(offline::NotAvailable(offline::describe(&source)).into());
+        }
+    }
+
     GIT_CACHE
         .get()
         .unwrap()
@@ -275,12 +348,25 @@ fn cmd_gitclone(matches: &clap::ArgMatches) -> Result<i32> {
         .repository_url(repository.clone())
         .sparse_paths(sparse_paths)
         .target_path(target_path)
-        .update(matches.get_flag("update"))
+        .update(matches.get_flag("update") && !offline::enabled())
         .do_clone()?;
 
     Ok(0)
 }
 
+fn cmd_download_archive(matches: &clap::ArgMatches) -> Result<i32> {
+    let archive = download::Archive {
+        url: matches.get_one::<String>("url").unwrap().clone(),
+        sha256: matches.get_one::<String>("sha256").unwrap().clone(),
+        strip_components: *matches.get_one::<usize>("strip-components").unwrap(),
+    };
+    let target_path = matches.get_one::<Utf8PathBuf>("target_path").unwrap();
+
+    archive.extract(target_path)?;
+
+    Ok(0)
+}
+
 fn cmd_build(
     matches: &clap::ArgMatches,
     global: bool,
@@ -385,7 +471,7 @@ fn cmd_build(
             .build_infos
             .iter()
             .filter(|build_info| {
-                builders.selects(&build_info.builder)
+                builders.selects_builder(&build_info.builder)
                     && apps.selects(&build_info.binary)
                     && build_info.tasks.contains_key(task)
             })
@@ -437,6 +523,9 @@ fn cmd_build(
         let task_name = task;
         let mut targets = Vec::new();
         let mut ninja_targets = Vec::new();
+        let artifacts = matches
+            .get_many::<String>("artifacts")
+            .map(|artifacts| artifacts.collect_vec());
 
         for build in builds {
             let task = build.tasks.get(task).unwrap();
@@ -444,6 +533,18 @@ fn cmd_build(
                 if task.build_app() {
                     let build_target = build.out.clone();
                     ninja_targets.push(build_target);
+                    // only build the artifacts the task uses, or that were
+                    // requested explicitly
+                    ninja_targets.extend(
+                        build
+                            .artifacts
+                            .values()
+                            .filter(|artifact| task.mentions(artifact.as_str(), &build.tasks))
+                            .cloned(),
+                    );
+                    if let Some(artifacts) = &artifacts {
+                        ninja_targets.extend(requested_artifacts(build, artifacts)?);
+                    }
                 }
                 targets.push((build, task));
             }
@@ -491,32 +592,61 @@ fn cmd_build(
             return Ok(1);
         }
     } else {
+        let selected = |build_info: &&BuildInfo| {
+            builders.selects_builder(&build_info.builder) && apps.selects(&build_info.binary)
+        };
+
         // build ninja target arguments, if necessary
-        let targets: Option<Vec<Utf8PathBuf>> = if let Selector::All = builders {
-            if let Selector::All = apps {
-                None
+        let targets: Option<Vec<Utf8PathBuf>> =
+            if let Some(artifacts) = matches.get_many::<String>("artifacts") {
+                let artifacts = artifacts.collect_vec();
+                let mut targets = Vec::new();
+                for build_info in builds.build_infos.iter().filter(selected) {
+                    targets.extend(requested_artifacts(build_info, &artifacts)?);
+                }
+                Some(targets)
+            } else if let Selector::All = builders {
+                if let Selector::All = apps {
+                    None
+                } else {
+                    // TODO: filter by app
+                    None
+                }
             } else {
-                // TODO: filter by app
-                None
-            }
-        } else {
-            Some(
-                builds
-                    .build_infos
-                    .iter()
-                    .filter_map(|build_info| {
-                        (builders.selects(&build_info.builder) && apps.selects(&build_info.binary))
-                            .then_some(build_info.out.clone())
-                    })
-                    .collect(),
-            )
-        };
+                Some(
+                    builds
+                        .build_infos
+                        .iter()
+                        .filter(selected)
+                        .flat_map(|build_info| {
+                            std::iter::once(&build_info.out).chain(build_info.artifacts.values())
+                        })
+                        .cloned()
+                        .collect(),
+                )
+            };
 
         ninja_run(ninja_build_file.as_path(), targets, jobs, keep_going)?;
     }
     Ok(0)
 }
 
+/// Returns the outputs of `artifacts` of `build_info`.
+fn requested_artifacts(build_info: &BuildInfo, artifacts: &[&String]) -> Result<Vec<Utf8PathBuf>> {
+    artifacts
+        .iter()
+        .map(|artifact| {
+            build_info.artifacts.get(*artifact).cloned().ok_or_else(|| {
+                anyhow!(
+                    "no artifact \"{artifact}\" for app \"{}\" on builder \"{}\"",
+                    build_info.binary,
+                    build_info.builder
+                )
+            })
+        })
+        .collect()
+}
+
 fn cmd_clean(matches: &clap::ArgMatches, global: bool, start_relpath: Utf8PathBuf) -> Result<i32> {
     let unused = matches.get_flag("unused");
     let build_dir = matches.get_one::<Utf8PathBuf>("build-dir").unwrap();
@@ -534,11 +664,112 @@ fn cmd_clean(matches: &clap::ArgMatches, global: bool, start_relpath: Utf8PathBu
     Ok(0)
 }
 
-fn cmd_inspect(matches: &clap::ArgMatches, project_file: Utf8PathBuf) -> Result<i32> {
+fn cmd_check(
+    matches: &clap::ArgMatches,
+    project_root: Utf8PathBuf,
+    project_file: Utf8PathBuf,
+) -> Result<i32> {
+    let build_dir = matches.get_one::<Utf8PathBuf>("build-dir").unwrap();
+    let builders = Selector::from(matches.get_many::<String>("builders"));
+    let apps = Selector::from(matches.get_many::<String>("apps"));
+
+    let settings = inspect::BuildSettings {
+        select: get_selects(matches),
+        disable: get_disables(matches),
+        require: get_requires(matches),
+        define: get_cli_vars(matches)?,
+    };
+
+    let result = check::CheckResult::from_project(
+        &project_root,
+        &project_file,
+        build_dir,
+        &builders,
+        &apps,
+        &settings,
+    )?;
+
+    if matches.get_flag("json") {
+        serde_json::to_writer_pretty(std::io::stdout(), &result)?;
+        println!();
+    } else {
+        result.write_text(std::io::stdout())?;
+    }
+
+    let mut failed = result.has_errors();
+    if failed {
+        error!("laze: some builds could not be configured");
+    }
+
+    if let Some(baseline) = matches.get_one::<Utf8PathBuf>("baseline") {
+        let baseline = check::CheckResult::from_file(baseline)?;
+        // pairs that were not selected on the command line are not compared
+        let regressions = result
+            .regressions(&baseline)
+            .into_iter()
+            .filter(|(builder, app, _)| builders.selects_builder(builder) && apps.selects(app))
+            .collect_vec();
+        for (builder, app, status) in &regressions {
+            match status {
+                Some(status) => error!(
+                    "laze: app \"{app}\" is not built for builder \"{builder}\" anymore: {status}"
+                ),
+                None => error!(
+                    "laze: app \"{app}\" for builder \"{builder}\" is missing (but in baseline)"
+                ),
+            }
+        }
+        failed |= !regressions.is_empty();
+    }
+
+    Ok(if failed { 1 } else { 0 })
+}
+
+fn cmd_lint(matches: &clap::ArgMatches, project_file: Utf8PathBuf) -> Result<i32> {
+    let build_dir = matches.get_one::<Utf8PathBuf>("build-dir").unwrap();
+    let (contexts, _, _) = data::load(&project_file, build_dir)?;
+
+    let lints = lint::lint(&contexts, build_dir);
+
+    if matches.get_flag("json") {
+        serde_json::to_writer_pretty(std::io::stdout(), &lints)?;
+        println!();
+    } else {
+        lint::write_text(std::io::stdout(), &lints)?;
+    }
+
+    let fail_level = if matches.get_flag("deny-warnings") {
+        lint::LintLevel::Warning
+    } else {
+        lint::LintLevel::Error
+    };
+
+    Ok(if lints.iter().any(|lint| lint.level >= fail_level) {
+        1
+    } else {
+        0
+    })
+}
+
+fn cmd_update(matches: &clap::ArgMatches, project_file: Utf8PathBuf) -> Result<i32> {
+    let build_dir = matches.get_one::<Utf8PathBuf>("build-dir").unwrap();
+
+    // loading handles all imports, re-resolving the ones being updated
+    data::load(&project_file, build_dir)?;
+
+    Ok(0)
+}
+
+fn cmd_inspect(
+    matches: &clap::ArgMatches,
+    project_root: Utf8PathBuf,
+    project_file: Utf8PathBuf,
+) -> Result<i32> {
     let build_dir = matches.get_one::<Utf8PathBuf>("build-dir").unwrap();
     match matches.subcommand() {
         Some(("builders", matches)) => {
-            let build_inspector = BuildInspector::from_project(project_file, build_dir.clone())?;
+            let build_inspector =
+                BuildInspector::from_project(project_root, project_file, build_dir.clone())?;
             if matches.get_flag("tree") {
                 build_inspector.write_tree(&std::io::stdout())?;
             } else {
@@ -548,6 +779,78 @@ fn cmd_inspect(matches: &clap::ArgMatches, project_file: Utf8PathBuf) -> Result<
                     .for_each(|builder| println!("{}", builder.name));
             }
         }
+        Some(("modules", matches)) => {
+            let build_inspector =
+                BuildInspector::from_project(project_root, project_file, build_dir.clone())?;
+            let modules = build_inspector.inspect_modules(matches.get_one::<String>("context"))?;
+            let modules = modules
+                .iter()
+                .map(|module| inspect::ModuleSummary::from(*module))
+                .collect_vec();
+            if matches.get_flag("json") {
+                serde_json::to_writer_pretty(std::io::stdout(), &modules)?;
+                println!();
+            } else {
+                for module in modules {
+                    module.write_text(std::io::stdout())?;
+                }
+            }
+        }
+        Some(("why", matches)) => {
+            let build_inspector =
+                BuildInspector::from_project(project_root, project_file, build_dir.clone())?;
+            let settings = inspect::BuildSettings {
+                select: get_selects(matches),
+                disable: get_disables(matches),
+                require: get_requires(matches),
+                ..Default::default()
+            };
+            build_inspector.write_why(
+                std::io::stdout(),
+                matches.get_one::<String>("builder").unwrap(),
+                matches.get_one::<String>("app").unwrap(),
+                matches.get_one::<String>("module").unwrap(),
+                &settings,
+            )?;
+        }
+        Some(("env", matches)) => {
+            let origin = matches.get_flag("origin");
+            let build_inspector =
+                BuildInspector::from_project(project_root, project_file, build_dir.clone())?;
+            let settings = inspect::BuildSettings {
+                select: get_selects(matches),
+                disable: get_disables(matches),
+                require: get_requires(matches),
+                define: get_cli_vars(matches)?,
+            };
+            build_inspector.write_env(
+                std::io::stdout(),
+                matches.get_one::<String>("builder").unwrap(),
+                matches.get_one::<String>("app").unwrap(),
+                matches.get_one::<String>("module"),
+                &settings,
+                matches.get_flag("json"),
+                origin,
+            )?;
+        }
+        Some(("graph", matches)) => {
+            let build_inspector =
+                BuildInspector::from_project(project_root, project_file, build_dir.clone())?;
+            let settings = inspect::BuildSettings {
+                select: get_selects(matches),
+                disable: get_disables(matches),
+                require: get_requires(matches),
+                ..Default::default()
+            };
+            let format = matches.get_one::<String>("format").unwrap().parse()?;
+            build_inspector.write_graph(
+                std::io::stdout(),
+                matches.get_one::<String>("builder").unwrap(),
+                matches.get_one::<String>("app").unwrap(),
+                &settings,
+                format,
+            )?;
+        }
         _ => (),
     };
     Ok(0)
@@ -576,6 +879,8 @@ fn get_cli_vars(build_matches: &clap::ArgMatches) -> Result<Option<Env>, Error>
             env.assign_from_string(assignment)?;
         }
 
+        env.set_origin("command line");
+
         Some(env)
     } else {
         None
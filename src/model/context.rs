@@ -5,6 +5,7 @@ use std::hash::{Hash, Hasher};
 
 use camino::Utf8PathBuf;
 
+use crate::location::Location;
 use crate::nested_env::EnvMap;
 use crate::Env;
 use crate::MergeOption;
@@ -29,11 +30,15 @@ pub struct Context {
     pub provided: Option<im::HashMap<String, IndexSet<String>>>,
 
     pub var_options: Option<im::HashMap<String, MergeOption>>,
+    pub archive: Option<bool>,
+    /// whether this builder was created from a variant of its parent
+    pub is_variant: bool,
 
     pub tasks: Option<HashMap<String, Task>>,
     pub env_early: Env,
     pub is_builder: bool,
     pub defined_in: Option<Utf8PathBuf>,
+    pub location: Option<Location>,
 }
 
 impl Context {
@@ -51,9 +56,12 @@ impl Context {
             env_early: Env::new(),
             rules: None,
             var_options: None,
+            archive: None,
+            is_variant: false,
             tasks: None,
             is_builder: false,
             defined_in: None,
+            location: None,
         }
     }
 
@@ -120,8 +128,8 @@ impl Context {
     }
 
     // This function collects all rules of a given context and all its parents.
-    // The resulting indexmap is indexed by the "in_" field (which should be an extension),
-    // or if absent, by name
+    // The resulting indexmap is indexed by each entry of the "in_" field (an
+    // extension or file name pattern), or if absent, by name
     pub fn collect_rules<'a>(
         &'a self,
         contexts: &'a ContextBag,
@@ -135,10 +143,8 @@ impl Context {
             if let Some(rules) = &parent.rules {
                 for (_, rule) in rules {
                     //println!("rule {}", rule.name);
-                    if let Some(rule_in) = rule.in_.as_ref() {
-                        result.insert(rule_in.clone(), rule);
-                    } else {
-                        result.insert(rule.name.clone(), rule);
+                    for key in rule.keys() {
+                        result.insert(key.clone(), rule);
                     }
                 }
             }
@@ -224,6 +230,21 @@ impl Context {
         format!("context::{}", context_name)
     }
 
+    /// Returns whether `selector` selects the variant builder named `name`
+    /// (`<base>:<value>:...`).
+    ///
+    /// `<base>` selects all variants of a builder, `<base>:<value>:...` those
+    /// having all of the given values.
+    pub fn variant_selected(selector: &str, name: &str) -> bool {
+        let mut values = name.split(':');
+        let mut selected = selector.split(':');
+        if values.next() != selected.next() {
+            return false;
+        }
+        let values = values.collect::<Vec<_>>();
+        !values.is_empty() && selected.all(|value| values.contains(&value))
+    }
+
     pub(crate) fn new_default() -> Context {
         let mut default = Context::new("default".to_string(), None);
         let default_module =
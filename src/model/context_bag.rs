@@ -1,11 +1,11 @@
 use std::collections::HashMap;
 use std::{iter::Filter, slice::Iter};
 
-use camino::Utf8PathBuf;
 use indexmap::IndexSet;
 use thiserror::Error;
 
 use super::{BlockAllow, Context, Module};
+use crate::location::{self, Location};
 
 #[derive(Default)]
 pub struct ContextBag {
@@ -21,28 +21,33 @@ pub enum IsAncestor {
 
 #[derive(Error, Debug, Clone)]
 pub enum ContextBagError {
-    #[error("{defined_in:?}: context \"{name}\" has unknown parent \"{parent_name}\"")]
+    #[error("context \"{name}\" has unknown parent \"{parent_name}\"")]
     UnknownParent {
-        defined_in: Utf8PathBuf,
+        location: Location,
         name: String,
         parent_name: String,
     },
-    #[error(r#"context name already defined in "{defined_in}""#)]
-    DuplicateContext { defined_in: Utf8PathBuf },
+    #[error(r#"context name already defined in {defined_in}"#)]
+    DuplicateContext {
+        location: Location,
+        defined_in: Location,
+    },
 
-    #[error(r#"{defined_in:?}: module "{name}": undefined context "{context_name}""#)]
+    #[error(r#"module "{name}": undefined context "{context_name}""#)]
     UndefinedModuleContext {
-        defined_in: Utf8PathBuf,
+        location: Location,
         name: String,
         context_name: String,
     },
 
-    #[error(r#"{defined_in:?}: module "{name}", context "{context_name}": module name already used in {conflict_in:?}"#)]
+    #[error(
+        r#"module "{name}", context "{context_name}": module name already used in {conflict_in}"#
+    )]
     DuplicateModule {
-        defined_in: Utf8PathBuf,
+        location: Location,
         name: String,
         context_name: String,
-        conflict_in: Utf8PathBuf,
+        conflict_in: Box<Location>,
     },
 
     #[error("context {name} is not a build context")]
@@ -51,6 +56,25 @@ pub enum ContextBagError {
     UnknownBuilder { name: String },
 }
 
+impl ContextBagError {
+    /// Where in the laze files this error happened
+    pub fn location(&self) -> Option<&Location> {
+        match self {
+            ContextBagError::UnknownParent { location, .. }
+            | ContextBagError::DuplicateContext { location, .. }
+            | ContextBagError::UndefinedModuleContext { location, .. }
+            | ContextBagError::DuplicateModule { location, .. } => Some(location),
+            _ => None,
+        }
+    }
+
+    /// Converts into an error pointing at [`ContextBagError::location`].
+    pub fn located(self) -> anyhow::Error {
+        let location = self.location().cloned();
+        location::locate(self.into(), location.as_ref())
+    }
+}
+
 impl ContextBag {
     pub fn new() -> ContextBag {
         Self::default()
@@ -71,7 +95,11 @@ impl ContextBag {
             if let Some(parent_name) = &context.parent_name {
                 let parent = self.context_map.get(&parent_name.clone()).ok_or_else(|| {
                     ContextBagError::UnknownParent {
-                        defined_in: context.defined_in.clone().unwrap(),
+                        location: context
+                            .location
+                            .as_ref()
+                            .unwrap()
+                            .entry(&["parent"], parent_name),
                         name: context.name.clone(),
                         parent_name: parent_name.into(),
                     }
@@ -164,9 +192,10 @@ impl ContextBag {
         is_builder: bool,
     ) -> Result<&mut Context, ContextBagError> {
         if let Some(context_id) = self.context_map.get(&context.name) {
-            let context = self.context_by_id(*context_id);
+            let other = self.context_by_id(*context_id);
             return Err(ContextBagError::DuplicateContext {
-                defined_in: context.defined_in.clone().unwrap(),
+                location: context.location.unwrap(),
+                defined_in: other.location.clone().unwrap(),
             });
         }
 
@@ -188,7 +217,11 @@ impl ContextBag {
     pub fn add_module(&mut self, mut module: Module) -> Result<(), ContextBagError> {
         let context_id = self.context_map.get(&module.context_name).ok_or_else(|| {
             ContextBagError::UndefinedModuleContext {
-                defined_in: module.defined_in.clone().unwrap(),
+                location: module
+                    .location
+                    .as_ref()
+                    .unwrap()
+                    .entry(&["context"], &module.context_name),
                 name: module.name.clone(),
                 context_name: module.context_name.clone(),
             }
@@ -198,10 +231,10 @@ impl ContextBag {
         match context.modules.entry(module.name.clone()) {
             indexmap::map::Entry::Occupied(other_module) => {
                 return Err(ContextBagError::DuplicateModule {
-                    defined_in: module.defined_in.clone().unwrap(),
+                    location: module.location.unwrap(),
                     name: module.name,
                     context_name: module.context_name,
-                    conflict_in: other_module.get().defined_in.clone().unwrap(),
+                    conflict_in: Box::new(other_module.get().location.clone().unwrap()),
                 })
             }
             indexmap::map::Entry::Vacant(entry) => {
@@ -245,19 +278,29 @@ impl ContextBag {
     ) -> Result<Vec<&Context>, ContextBagError> {
         let mut res = Vec::new();
         for name in names {
+            // a builder's variants are selected by the builder's name, or
+            // by some of their values (`builder:release`)
+            let variants = self
+                .builders()
+                .filter(|builder| {
+                    builder.is_variant && Context::variant_selected(name, &builder.name)
+                })
+                .collect::<Vec<_>>();
+
             match self.get_by_name(name) {
+                Some(context) if context.is_builder => res.push(context),
+                _ if !variants.is_empty() => res.extend(variants),
                 Some(context) => {
-                    if context.is_builder {
-                        res.push(context);
-                    } else {
-                        return Err(ContextBagError::NotABuildContext {
-                            name: context.name.clone(),
-                        });
-                    }
+                    return Err(ContextBagError::NotABuildContext {
+                        name: context.name.clone(),
+                    });
                 }
                 None => return Err(ContextBagError::UnknownBuilder { name: name.into() }),
             }
         }
+        // selectors may overlap
+        let mut seen = IndexSet::new();
+        res.retain(|context| seen.insert(&context.name));
         Ok(res)
     }
 
@@ -28,3 +28,17 @@ where
         }
     }
 }
+
+impl<T> std::fmt::Display for Dependency<T>
+where
+    T: Display,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Dependency::Hard(name) => write!(f, "{name}"),
+            Dependency::Soft(name) => write!(f, "?{name}"),
+            Dependency::IfThenHard(other, name) => write!(f, "{name} (if {other})"),
+            Dependency::IfThenSoft(other, name) => write!(f, "?{name} (if {other})"),
+        }
+    }
+}
@@ -11,7 +11,7 @@ pub use blockallow::BlockAllow;
 pub use context::Context;
 pub use context_bag::{ContextBag, ContextBagError, IsAncestor};
 pub use dependency::Dependency;
-pub use module::{CustomBuild, Module};
+pub use module::{CustomBuild, Module, SourceOverride};
 pub use rule::Rule;
 pub use shared::VarExportSpec;
 pub use task::{Task, TaskError};
@@ -2,18 +2,20 @@ use std::collections::{HashMap, HashSet};
 use std::fmt;
 use std::hash::{Hash, Hasher};
 
-use anyhow::Error;
+use anyhow::{anyhow, Error};
 use camino::{Utf8Path, Utf8PathBuf};
 use indexmap::{indexset, IndexMap, IndexSet};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use crate::build::ResolverResult;
 use crate::download;
+use crate::location::{Locate, Location};
 use crate::nested_env;
 use crate::nested_env::Env;
 use crate::Dependency;
 
-use super::Task;
+use super::{Rule, Task};
 
 #[derive(Clone, Eq, Debug, Default)]
 pub struct Module {
@@ -35,10 +37,18 @@ pub struct Module {
 
     pub sources: Vec<String>,
     pub sources_optional: Option<IndexMap<String, Vec<String>>>,
+    pub source_overrides: IndexMap<String, SourceOverride>,
+    pub rule: Option<String>,
+    pub rules: Option<IndexMap<String, Rule>>,
+    pub export_rules: bool,
 
     pub tasks: HashMap<String, Task>,
 
     pub build: Option<CustomBuild>,
+    pub archive: Option<bool>,
+    pub whole_archive: bool,
+    pub libs: Vec<String>,
+    pub link_deps: Vec<String>,
 
     pub env_local: Env,
     pub env_export: Env,
@@ -48,6 +58,7 @@ pub struct Module {
     pub download: Option<download::Download>,
     pub context_id: Option<usize>,
     pub defined_in: Option<Utf8PathBuf>,
+    pub location: Option<Location>,
     pub relpath: Option<Utf8PathBuf>,
     pub srcdir: Option<Utf8PathBuf>,
     pub build_dep_files: Option<IndexSet<Utf8PathBuf>>,
@@ -201,6 +212,65 @@ impl Module {
         (module_env, build_dep_modules)
     }
 
+    /// Returns the modules this module has to be built after, i.e., its
+    /// (recursive) imports that are build dependencies.
+    ///
+    /// These are the same as returned by [`Module::build_env`], without
+    /// assembling the environment.
+    pub fn build_deps<'a>(&'a self, resolver_result: &'a ResolverResult) -> IndexSet<&'a Module> {
+        self.get_imports_recursive(&resolver_result.modules, &resolver_result.providers, None)
+            .into_iter()
+            .filter(|dep| *dep != self && dep.is_build_dep)
+            .collect()
+    }
+
+    /// Adds the rules this module can use to `result`, i.e., the exported
+    /// rules of all modules it imports, then its own.
+    ///
+    /// Like with [`Context::collect_rules`](super::Context::collect_rules),
+    /// later rules replace earlier ones with the same input (or name), so a
+    /// module's own rules win over imported ones, which win over the builder's.
+    ///
+    /// Fails if two imported modules export rules with the same input (or
+    /// name), as which one applies would depend on the import order.
+    pub fn collect_rules<'a>(
+        &'a self,
+        resolver_result: &'a ResolverResult,
+        result: &mut IndexMap<String, &'a Rule>,
+    ) -> Result<(), Error> {
+        let modules = &resolver_result.modules;
+        let providers = &resolver_result.providers;
+
+        // map key -> module that exported a rule for it
+        let mut exported: IndexMap<&String, &Module> = IndexMap::new();
+
+        for dep in self.get_imports_recursive(modules, providers, None) {
+            if dep != self && !dep.export_rules {
+                continue;
+            }
+            for rule in dep.rules.iter().flat_map(|rules| rules.values()) {
+                for key in rule.keys() {
+                    if dep != self {
+                        if let Some(other) = exported.insert(key, dep) {
+                            if other != dep {
+                                return Err(anyhow!(
+                                    "module \"{}\" imports rules for \"{key}\" from both \"{}\" and \"{}\"",
+                                    self.name,
+                                    other.name,
+                                    dep.name
+                                ))
+                                .located(self.location.as_ref());
+                            }
+                        }
+                    }
+                    result.insert(key.clone(), rule);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
     fn create_module_define(&self) -> String {
         self.name
             .chars()
@@ -314,9 +384,22 @@ impl fmt::Display for Module {
     }
 }
 
-#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+/// Variables and rule used for a single source file
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct SourceOverride {
+    /// merged over the module's env
+    pub env: Option<Env>,
+    /// name of the rule to use instead of the one matching the extension
+    pub rule: Option<String>,
+}
+
+/// A custom build step
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize, JsonSchema)]
 pub struct CustomBuild {
+    /// Dependency file written by the command, for header dependency tracking.
     pub gcc_deps: Option<String>,
+    /// Commands to run.
     pub cmd: Vec<String>,
+    /// Files created by the commands.
     pub out: Option<Vec<String>>,
 }
@@ -3,8 +3,12 @@ use std::collections::HashMap;
 use std::convert::From;
 use std::hash::{Hash, Hasher};
 
+use camino::Utf8Path;
+use glob::Pattern;
+use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
 
+use crate::location::Location;
 use crate::nested_env::EnvMap;
 use crate::serde_bool_helpers::{default_as_false, default_as_true};
 
@@ -16,8 +20,9 @@ pub struct Rule {
 
     pub help: Option<String>,
 
-    #[serde(rename = "in")]
-    pub in_: Option<String>,
+    /// input extensions or file name patterns (e.g., `*.pb.c`)
+    #[serde(rename = "in", default)]
+    pub in_: Vec<String>,
     pub out: Option<String>,
     pub context: Option<String>,
     pub options: Option<HashMap<String, String>>,
@@ -28,10 +33,18 @@ pub struct Rule {
     pub description: Option<String>,
     pub export: Option<Vec<VarExportSpec>>,
 
+    /// name of the artifact this rule creates from the linked app (e.g., `hex`)
+    pub artifact: Option<String>,
+    /// artifact this rule creates its artifact from, instead of the linked app
+    pub artifact_from: Option<String>,
+
     #[serde(default = "default_as_false")]
     pub always: bool,
     #[serde(default = "default_as_true")]
     pub shareable: bool,
+
+    #[serde(skip)]
+    pub location: Option<Location>,
 }
 
 impl Rule {
@@ -40,6 +53,76 @@ impl Rule {
         Ok(ninja_rule.build().unwrap().expand(env)?.named())
     }
 
+    /// Returns the keys this rule is looked up by: its inputs, or its name if
+    /// it has none.
+    pub fn keys(&self) -> &[String] {
+        if self.in_.is_empty() {
+            std::slice::from_ref(&self.name)
+        } else {
+            &self.in_
+        }
+    }
+
+    /// Returns whether rule input `input` (an extension or a file name
+    /// pattern) matches `source`.
+    pub fn input_matches(input: &str, source: &str) -> bool {
+        let source = Utf8Path::new(source);
+        if is_pattern(input) {
+            Pattern::new(input).is_ok_and(|pattern| {
+                source
+                    .file_name()
+                    .is_some_and(|file_name| pattern.matches(file_name))
+            })
+        } else {
+            source.extension() == Some(input)
+        }
+    }
+
+    /// Returns the rule for building `source`, out of rules collected by
+    /// [`Context::collect_rules`](super::Context::collect_rules).
+    ///
+    /// File name patterns are matched before extensions, as they are more
+    /// specific (`*.pb.c` vs. `c`).
+    pub fn find_by_input<'a>(rules: &IndexMap<String, &'a Rule>, source: &str) -> Option<&'a Rule> {
+        rules
+            .iter()
+            .find(|(input, _)| is_pattern(input) && Self::input_matches(input, source))
+            .map(|(_, rule)| rule)
+            .or_else(|| {
+                Utf8Path::new(source)
+                    .extension()
+                    .and_then(|ext| rules.get(ext))
+            })
+            .copied()
+    }
+
+    /// Returns the rules that turn `source` into its final output, starting
+    /// with `first`.
+    ///
+    /// As long as a rule with an `out` extension matches the previous output
+    /// (e.g., `msg.pb.c` generated from `msg.proto`), it is added to the
+    /// chain. Returns `None` if the chain gets longer than `max_len`.
+    pub fn chain<'a>(
+        rules: &IndexMap<String, &'a Rule>,
+        first: &'a Rule,
+        source: &str,
+        max_len: usize,
+    ) -> Option<Vec<&'a Rule>> {
+        let mut chain = vec![first];
+        let mut output = Utf8Path::new(source).with_extension(first.out.as_ref()?);
+        while let Some(next) = Self::find_by_input(rules, output.as_str()) {
+            let Some(out) = &next.out else {
+                break;
+            };
+            if chain.len() == max_len {
+                return None;
+            }
+            chain.push(next);
+            output.set_extension(out);
+        }
+        Some(chain)
+    }
+
     /// get rule description
     ///
     /// if no description is set, uses this rule's name
@@ -52,6 +135,10 @@ impl Rule {
     }
 }
 
+fn is_pattern(input: &str) -> bool {
+    input.contains(['*', '?', '['])
+}
+
 impl Hash for Rule {
     fn hash<H: Hasher>(&self, state: &mut H) {
         /* rules are unique per context subtree, so hashing the name is
@@ -87,3 +174,76 @@ impl<'a> From<&'a Rule> for crate::ninja::NinjaRuleBuilder<'a> {
         builder
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(name: &str) -> Rule {
+        Rule {
+            name: name.into(),
+            cmd: String::new(),
+            help: None,
+            in_: Vec::new(),
+            out: None,
+            context: None,
+            options: None,
+            gcc_deps: None,
+            rspfile: None,
+            rspfile_content: None,
+            pool: None,
+            description: None,
+            export: None,
+            artifact: None,
+            artifact_from: None,
+            always: false,
+            shareable: true,
+            location: None,
+        }
+    }
+
+    #[test]
+    fn test_find_by_input() {
+        let (cc, pb) = (rule("CC"), rule("PB"));
+        let mut rules = IndexMap::new();
+        rules.insert("c".to_string(), &cc);
+        rules.insert("inc".to_string(), &cc);
+        rules.insert("*.pb.c".to_string(), &pb);
+
+        let find = |source| Rule::find_by_input(&rules, source).map(|rule| rule.name.as_str());
+        assert_eq!(find("main.c"), Some("CC"));
+        assert_eq!(find("sub/table.inc"), Some("CC"));
+        assert_eq!(find("sub/msg.pb.c"), Some("PB"));
+        assert_eq!(find("pb.c/main.c"), Some("CC"));
+        assert_eq!(find("README"), None);
+    }
+
+    #[test]
+    fn test_chain() {
+        let rule_with = |name, in_: &str, out: Option<&str>| Rule {
+            in_: vec![in_.into()],
+            out: out.map(Into::into),
+            ..rule(name)
+        };
+        let protoc = rule_with("PROTOC", "proto", Some("pb.c"));
+        let cc = rule_with("CC", "c", Some("o"));
+        let link = rule_with("LINK", "o", None);
+        let cpp = rule_with("CPP", "i", Some("i"));
+        let mut rules = IndexMap::new();
+        for rule in [&protoc, &cc, &link, &cpp] {
+            rules.insert(rule.in_[0].clone(), rule);
+        }
+
+        let chain = |first, source| {
+            Rule::chain(&rules, first, source, 4).map(|chain| {
+                chain
+                    .iter()
+                    .map(|rule| rule.name.as_str())
+                    .collect::<Vec<_>>()
+            })
+        };
+        assert_eq!(chain(&cc, "main.c"), Some(vec!["CC"]));
+        assert_eq!(chain(&protoc, "msg.proto"), Some(vec!["PROTOC", "CC"]));
+        assert_eq!(chain(&cpp, "loop.i"), None);
+    }
+}
@@ -10,6 +10,7 @@ use log::debug;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
+use crate::location::Location;
 use crate::nested_env::{self, EnvMap};
 use crate::serde_bool_helpers::{default_as_false, default_as_true};
 use crate::subst_ext::{substitute, IgnoreMissing, LocalVec};
@@ -30,6 +31,8 @@ pub struct Task {
     #[serde(default = "default_as_false")]
     pub ignore_ctrl_c: bool,
     pub workdir: Option<String>,
+    #[serde(skip)]
+    pub location: Option<Location>,
 }
 
 #[derive(Error, Debug, Serialize, Deserialize, Clone)]
@@ -49,6 +52,54 @@ impl Task {
         self.build
     }
 
+    /// Returns whether a command or export of this task, or of a subtask it
+    /// calls, contains `text` (e.g., the path of an artifact).
+    pub fn mentions(
+        &self,
+        text: &str,
+        all_tasks: &IndexMap<String, Result<Task, TaskError>>,
+    ) -> bool {
+        self._mentions(text, all_tasks, &mut Vec::new())
+    }
+
+    fn _mentions<'a>(
+        &'a self,
+        text: &str,
+        all_tasks: &'a IndexMap<String, Result<Task, TaskError>>,
+        seen: &mut Vec<&'a str>,
+    ) -> bool {
+        let in_export = self
+            .export
+            .iter()
+            .flatten()
+            .filter_map(|spec| spec.content.as_ref())
+            .any(|content| content.contains(text));
+        if in_export {
+            return true;
+        }
+
+        self.cmd.iter().any(|cmd| {
+            if cmd.contains(text) {
+                return true;
+            }
+            // follow subtasks, once each
+            let Some(name) = cmd
+                .strip_prefix(":")
+                .and_then(|cmd| cmd.split_whitespace().next())
+            else {
+                return false;
+            };
+            let Some((name, Ok(subtask))) = all_tasks.get_key_value(name) else {
+                return false;
+            };
+            if seen.contains(&name.as_str()) {
+                return false;
+            }
+            seen.push(name);
+            subtask._mentions(text, all_tasks, seen)
+        })
+    }
+
     pub fn execute(
         &self,
         start_dir: &Path,
@@ -0,0 +1,68 @@
+use std::borrow::Cow;
+
+use super::eval_context::EvalContext;
+use super::expand::{expand, ExpandError, IfMissing};
+use super::EnvMap;
+
+/// Evaluates a boolean expression like `${host::os} == "linux"`.
+///
+/// Variables (`${name}`) are replaced by string literals of their values,
+/// so they don't need quoting. Unknown variables are an error.
+pub fn eval_condition(condition: &str, env: &EnvMap) -> Result<bool, ExpandError> {
+    let quoted: EnvMap = env
+        .iter()
+        .map(|(name, value)| {
+            let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+            (*name, Cow::from(format!("\"{value}\"")))
+        })
+        .collect();
+
+    let expanded = expand(condition, &quoted, IfMissing::Error)?;
+    evalexpr::eval_boolean_with_context(&expanded, &EvalContext::new(env))
+        .map_err(ExpandError::Expr)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env() -> EnvMap<'static> {
+        let mut env = EnvMap::new();
+        env.insert("host::os", "linux".into());
+        env.insert("quoted", "a \"b\"".into());
+        env
+    }
+
+    #[test]
+    fn variables() {
+        assert_eq!(eval_condition("${host::os} == \"linux\"", &env()), Ok(true));
+        assert_eq!(
+            eval_condition("${host::os} != \"linux\"", &env()),
+            Ok(false)
+        );
+        assert_eq!(
+            eval_condition("${quoted} == \"a \\\"b\\\"\"", &env()),
+            Ok(true)
+        );
+    }
+
+    #[test]
+    fn functions() {
+        assert_eq!(
+            eval_condition("str::to_uppercase(${host::os}) == \"LINUX\"", &env()),
+            Ok(true)
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            eval_condition("${unknown} == \"x\"", &env()),
+            Err(ExpandError::Missing("unknown".into()))
+        );
+        assert!(matches!(
+            eval_condition("${host::os}", &env()),
+            Err(ExpandError::Expr(_))
+        ));
+    }
+}
@@ -63,9 +63,9 @@ where
 
     let expanded = expand_recursive::<SI>(s, env, seen, if_missing)?;
     let eval_context = EvalContext::new(env);
-    Ok(expanded
+    expanded
         .eval_with_context(eval_context)
-        .map_err(ExpandError::Expr)?)
+        .map_err(ExpandError::Expr)
 }
 
 fn expand_recursive<'a, SI>(
@@ -1,34 +1,64 @@
 use std::borrow::Cow;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
 
 use anyhow::{anyhow, Context, Error};
 use evalexpr::EvalexprError;
 use im::{hashmap::Entry, vector, Vector};
-use itertools::join;
+use itertools::{join, Itertools};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
+mod condition;
 mod eval_context;
 mod expand;
 mod expr;
 pub use expr::Eval;
 
+pub use condition::eval_condition;
 pub use expand::{expand, expand_eval, IfMissing};
 
 pub type EnvMap<'a> = std::collections::HashMap<&'a str, Cow<'a, str>>;
 
-#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize, Hash, Default)]
+/// Describes where a variable value was set, e.g., `context "foo" (laze-project.yml)`
+pub type Origin = Arc<str>;
+
+/// Origins of a variable's value, one per list element (or one for a single value)
+type Origins = Vector<Option<Origin>>;
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default, JsonSchema)]
 pub struct Env {
     #[serde(flatten)]
+    #[schemars(with = "std::collections::HashMap<String, EnvKey>")]
     inner: im::HashMap<String, EnvKey>,
+    /// where each value came from, if tracked (values without origin were set by laze)
+    #[serde(skip)]
+    origins: Option<im::HashMap<String, Origins>>,
+}
+
+// origins are informational only, so they're ignored for comparison and hashing
+impl PartialEq for Env {
+    fn eq(&self, other: &Self) -> bool {
+        self.inner == other.inner
+    }
+}
+
+impl Eq for Env {}
+
+impl Hash for Env {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.inner.hash(state);
+    }
 }
 
-#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize, Hash)]
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize, Hash, JsonSchema)]
 #[serde(untagged, expecting = "expected single value or array of values")]
 pub enum EnvKey {
     Single(String),
-    List(Vector<String>),
+    List(#[schemars(with = "Vec<String>")] Vector<String>),
 }
 
-#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Clone, Default)]
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Clone, Default, JsonSchema)]
 pub struct MergeOption {
     from: Option<String>,
     joiner: Option<String>,
@@ -39,6 +69,14 @@ pub struct MergeOption {
 }
 
 impl EnvKey {
+    /// Returns the number of values (1 for a single value).
+    fn len(&self) -> usize {
+        match self {
+            EnvKey::Single(_) => 1,
+            EnvKey::List(list) => list.len(),
+        }
+    }
+
     fn merge(&self, other: &EnvKey) -> EnvKey {
         match self {
             EnvKey::Single(_) => other.clone(),
@@ -121,11 +159,16 @@ impl Env {
     pub fn new() -> Self {
         Self {
             inner: im::HashMap::new(),
+            origins: None,
         }
     }
 
     pub fn merge(&mut self, other: &Env) {
+        let track = self.origins.is_some() || other.origins.is_some();
         for (key, value) in other.inner.iter() {
+            if track {
+                self.merge_origins(key, value, other);
+            }
             match self.entry(key.clone()) {
                 Entry::Vacant(e) => {
                     e.insert(value.clone());
@@ -138,6 +181,102 @@ impl Env {
         }
     }
 
+    /// Merges the origins of `other`'s `key` into ours.
+    ///
+    /// Needs to be called *before* the values get merged.
+    fn merge_origins(&mut self, key: &String, value: &EnvKey, other: &Env) {
+        let other_origins = other.origins_padded(key, value.len());
+        let merged = match (self.inner.get(key), value) {
+            (Some(EnvKey::List(list)), EnvKey::List(_)) => {
+                let mut merged = self.origins_padded(key, list.len());
+                merged.append(other_origins);
+                merged
+            }
+            _ => other_origins,
+        };
+        self.origins
+            .get_or_insert_with(im::HashMap::new)
+            .insert(key.clone(), merged);
+    }
+
+    /// Returns `key`'s origins, padded or truncated to `len`.
+    ///
+    /// Values added without origin (e.g., using [`Env::entry()`]) can make the
+    /// origins shorter than the actual values.
+    fn origins_padded(&self, key: &str, len: usize) -> Origins {
+        let mut origins = self
+            .origins
+            .as_ref()
+            .and_then(|origins| origins.get(key))
+            .cloned()
+            .unwrap_or_default();
+        if origins.len() > len {
+            origins.truncate(len);
+        }
+        while origins.len() < len {
+            origins.push_back(None);
+        }
+        origins
+    }
+
+    /// Sets the origin of all values to `origin`.
+    pub fn set_origin(&mut self, origin: &str) {
+        let origin = Origin::from(origin);
+        self.origins = Some(
+            self.inner
+                .iter()
+                .map(|(key, value)| {
+                    (
+                        key.clone(),
+                        Vector::from(vec![Some(origin.clone()); value.len()]),
+                    )
+                })
+                .collect(),
+        );
+    }
+
+    /// Returns this environment with all values' origin set to `origin`.
+    pub fn with_origin(&self, origin: &str) -> Env {
+        let mut res = self.clone();
+        res.set_origin(origin);
+        res
+    }
+
+    /// Returns the origins of `key`'s values, one per list element.
+    ///
+    /// Returns `None` if `key` is not set.
+    /// An element is `None` if laze set the value.
+    pub fn origins(&self, key: &str) -> Option<Vec<Option<&Origin>>> {
+        let origins = self.origins.as_ref().and_then(|origins| origins.get(key));
+        let len = self.inner.get(key)?.len();
+        Some(
+            (0..len)
+                .map(|i| {
+                    origins
+                        .and_then(|origins| origins.get(i))
+                        .and_then(Option::as_ref)
+                })
+                .collect(),
+        )
+    }
+
+    /// Describes variable `key` for error messages, including its origins, if known.
+    fn describe(&self, key: &str) -> String {
+        let origins = self
+            .origins(key)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .unique()
+            .join(", ");
+
+        if origins.is_empty() {
+            format!("variable \"{key}\"")
+        } else {
+            format!("variable \"{key}\" (set by {origins})")
+        }
+    }
+
     pub fn flatten(&self) -> Result<EnvMap<'_>, Error> {
         self.inner
             .iter()
@@ -146,7 +285,7 @@ impl Env {
                     Ok(v) => Ok((key.as_str(), v)),
                     Err(e) => Err(e),
                 }
-                .with_context(|| format!("variable \"{key}\""))
+                .with_context(|| self.describe(key))
             })
             .collect::<Result<EnvMap, Error>>()
     }
@@ -170,7 +309,7 @@ impl Env {
                         Err(e) => Err(e),
                     }
                 }
-                .with_context(|| format!("variable \"{key}\""))
+                .with_context(|| self.describe(key))
             })
             .collect::<Result<EnvMap, Error>>()?;
 
@@ -182,13 +321,14 @@ impl Env {
 
                 let flattened = other_value
                     .flatten_with_opts(merge_opt)
-                    .with_context(|| format!("variable \"{key}\""))?;
+                    .with_context(|| self.describe(key))?;
 
                 let previous = result.insert(key, flattened);
 
                 if previous.is_some() {
                     return Err(anyhow!(
-                        "variable \"{key}\" has both values and var_option `from`"
+                        "{} has both values and var_option `from`",
+                        self.describe(key)
                     ));
                 }
             }
@@ -239,6 +379,9 @@ impl Env {
     }
 
     pub fn insert<T: Into<EnvKey>>(&mut self, key: String, value: T) -> Option<EnvKey> {
+        if let Some(origins) = &mut self.origins {
+            origins.remove(&key);
+        }
         self.inner.insert(key, value.into())
     }
 
@@ -583,4 +726,34 @@ mod tests {
             ]),
         );
     }
+
+    #[test]
+    fn test_merge_origins() {
+        let mut lower = Env::new();
+        lower.insert("LIST".to_string(), EnvKey::List(vector!["a".to_string()]));
+        lower.insert("SINGLE".to_string(), "lower");
+        lower.set_origin("lower");
+
+        let mut upper = Env::new();
+        upper.insert("LIST".to_string(), EnvKey::List(vector!["b".to_string()]));
+        upper.insert("SINGLE".to_string(), "upper");
+        upper.set_origin("upper");
+
+        // values without origin
+        let mut unknown = Env::new();
+        unknown.insert("LIST".to_string(), EnvKey::List(vector!["c".to_string()]));
+
+        lower.merge(&upper);
+        lower.merge(&unknown);
+
+        let origin = |s: &str| Origin::from(s);
+        assert_eq!(
+            lower.origins("LIST").unwrap(),
+            vec![Some(&origin("lower")), Some(&origin("upper")), None]
+        );
+        assert_eq!(
+            lower.origins("SINGLE").unwrap(),
+            vec![Some(&origin("upper"))]
+        );
+    }
 }
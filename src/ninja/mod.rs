@@ -248,19 +248,29 @@ impl<'a> NinjaBuildBuilder<'a> {
     }
 }
 
+/// Returns `path` as written in `build` statements, escaping characters ninja
+/// would otherwise split paths at (e.g., `:` of variant builder names).
+fn escape_path(path: &Utf8Path) -> String {
+    path.as_std_path()
+        .to_slash()
+        .unwrap()
+        .replace(':', "$:")
+        .replace(' ', "$ ")
+}
+
 impl fmt::Display for NinjaBuild<'_> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "build")?;
 
         for out in &self.outs {
-            write!(f, " {}", out.as_std_path().to_slash().unwrap())?;
+            write!(f, " {}", escape_path(out))?;
         }
 
         write!(f, ": $\n    {}", self.rule)?;
 
         if let Some(inputs) = &self.inputs {
             for path in inputs {
-                write!(f, " $\n    {}", path.as_std_path().to_slash().unwrap())?;
+                write!(f, " $\n    {}", escape_path(path))?;
             }
         }
 
@@ -268,7 +278,7 @@ impl fmt::Display for NinjaBuild<'_> {
             write!(f, " $\n    |")?;
             if let Some(list) = &self.deps {
                 for entry in list {
-                    write!(f, " $\n    {}", entry.as_std_path().to_slash().unwrap())?;
+                    write!(f, " $\n    {}", escape_path(entry))?;
                 }
             }
             if self.always {
@@ -489,6 +499,24 @@ mod tests {
         );
     }
 
+    #[test]
+    fn build_escaped() {
+        let out = Utf8PathBuf::from("build/board:release/my app.elf");
+        let rule = NinjaBuildBuilder::default()
+            .rule("LINK")
+            .out(out.as_path())
+            .build()
+            .unwrap();
+        assert_eq!(
+            concat!(
+                "build build/board$:release/my$ app.elf: $\n",
+                "    LINK\n",
+                "\n"
+            ),
+            format!("{}", rule)
+        );
+    }
+
     #[test]
     fn build_with_input() {
         let testc = Utf8PathBuf::from("test.c");
@@ -0,0 +1,142 @@
+//! This module implements offline mode (`--offline`).
+//!
+//! In offline mode, imports and module downloads may only come from the build
+//! directory or from the existing git cache. Anything else is reported as
+//! missing instead of being fetched.
+
+use std::process::Command;
+use std::sync::OnceLock;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use thiserror::Error;
+
+use crate::download::{Git, Source};
+
+/// The git cache directory, set if offline mode is enabled.
+static GIT_CACHE_DIR: OnceLock<Utf8PathBuf> = OnceLock::new();
+
+pub fn enable(git_cache_dir: Utf8PathBuf) {
+    GIT_CACHE_DIR.set(git_cache_dir).ok();
+}
+
+pub fn enabled() -> bool {
+    GIT_CACHE_DIR.get().is_some()
+}
+
+/// Something that would have to be fetched, but cannot be in offline mode.
+#[derive(Error, Debug)]
+#[error("{0} is not available offline")]
+pub struct NotAvailable(pub String);
+
+/// Returns the error listing everything that would have to be fetched.
+pub fn missing_error(missing: &[String]) -> anyhow::Error {
+    let mut msg = "offline mode, but these would have to be fetched:".to_string();
+    for missing in missing {
+        msg.push_str("\n  - ");
+        msg.push_str(missing);
+    }
+    anyhow::anyhow!(msg)
+}
+
+/// Returns a description of `source` for listing it as missing.
+pub fn describe(source: &Source) -> String {
+    match source {
+        Source::Git(Git::Commit { url, commit }) => format!("git {url}@{commit}"),
+        Source::Git(Git::Branch { url, branch }) => format!("git {url} (branch {branch})"),
+        Source::Git(Git::Tag { url, tag }) => format!("git {url} (tag {tag})"),
+        Source::Git(Git::Default { url }) => format!("git {url}"),
+        Source::Archive(archive) => format!("archive {}", archive.url),
+        Source::Laze(name) => format!("laze {name}"),
+    }
+}
+
+/// Returns the path of the git cache's mirror of `url`, or `None` for local
+/// repositories (which the git cache clones directly).
+///
+/// This follows `git-cache`'s layout (`<host>/<path>.git`), which doesn't
+/// expose it. `src/tests/70_offline` checks it against a cache primed by
+/// `git-cache` itself.
+fn cache_path(cache_dir: &Utf8Path, url: &str) -> Option<Utf8PathBuf> {
+    let (host, path) = if let Ok(url) = url::Url::parse(url) {
+        if url.scheme() == "file" {
+            return None;
+        }
+        (url.host_str()?.to_string(), url.path()[1..].to_string())
+    } else {
+        // scp-like syntax, `user@host:path`
+        let (user_host, path) = url.split_once(':')?;
+        let (_user, host) = user_host.split_once('@')?;
+        (host.to_string(), path.to_string())
+    };
+
+    let mut path = cache_dir.join(host).join(path);
+    path.set_extension("git");
+    Some(path)
+}
+
+/// Returns whether `source` can be provided without fetching.
+pub fn available(source: &Source) -> bool {
+    if !enabled() {
+        return true;
+    }
+    match source {
+        Source::Git(git) => git_cache_has(git),
+        Source::Archive(archive) => archive.is_cached(),
+        Source::Laze(_) => true,
+    }
+}
+
+/// Returns whether the git cache can provide `git` without fetching.
+fn git_cache_has(git: &Git) -> bool {
+    cached_commit(git).is_some()
+}
+
+/// Returns the commit `git` refers to in the git cache, if it is there.
+pub fn cached_commit(git: &Git) -> Option<String> {
+    let cache_dir = GIT_CACHE_DIR.get()?;
+    let mirror = cache_path(cache_dir, git.url())?;
+    if !mirror.is_dir() {
+        return None;
+    }
+
+    let rev = match git {
+        Git::Commit { commit, .. } => commit.clone(),
+        Git::Branch { branch, .. } => format!("refs/heads/{branch}"),
+        Git::Tag { tag, .. } => format!("refs/tags/{tag}"),
+        Git::Default { .. } => "HEAD".to_string(),
+    };
+
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(&mirror)
+        .arg("rev-parse")
+        .arg("--verify")
+        .arg("--quiet")
+        .arg(format!("{rev}^{{commit}}"))
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8(output.stdout).ok()?.trim().to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_cache_path() {
+        let cache_dir = Utf8Path::new("/cache");
+        assert_eq!(
+            cache_path(cache_dir, "https://github.com/kaspar030/laze"),
+            Some("/cache/github.com/kaspar030/laze.git".into())
+        );
+        assert_eq!(
+            cache_path(cache_dir, "git@github.com:kaspar030/laze.git"),
+            Some("/cache/github.com/kaspar030/laze.git".into())
+        );
+        assert_eq!(cache_path(cache_dir, "file:///tmp/repo"), None);
+        assert_eq!(cache_path(cache_dir, "./repo"), None);
+    }
+}
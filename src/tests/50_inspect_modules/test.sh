@@ -0,0 +1,15 @@
+#!/bin/sh
+
+. ../test-common.sh
+
+cleanup
+
+run_laze inspect modules
+
+${LAZE} inspect modules --context other --json > stdout.json
+diff -q EXPECTED_STDOUT_JSON stdout.json
+
+echo TEST_OK
+
+cleanup
+rm -f stdout.json
@@ -0,0 +1,22 @@
+#!/bin/sh
+
+. ../test-common.sh
+
+cleanup
+
+for module in middle provider_a provider_b some_interface disabled_module conflicted only_used; do
+    ${LAZE} inspect why ${module} -b default -a app >> stdout
+done
+
+${LAZE} inspect why provider_b -b default -a app -d provider_b >> stdout
+${LAZE} inspect why middle -b default -a blocked_app >> stdout
+
+diff -q EXPECTED_STDOUT stdout
+
+if ${LAZE} inspect why nonexistent -b default -a app; then
+    false
+fi
+
+echo TEST_OK
+
+cleanup
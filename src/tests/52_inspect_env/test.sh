@@ -0,0 +1,26 @@
+#!/bin/sh
+
+. ../test-common.sh
+
+cleanup
+
+# filter out host specific variables
+${LAZE} inspect env -b default -a app -DCFLAGS+=-g \
+    | grep -v -e "^LAZE_BIN" -e "^project-root" -e "^host::" > stdout
+diff -q EXPECTED_STDOUT stdout
+
+${LAZE} inspect env -b default -a app --module foo --json > stdout
+grep -q '"value": "-Os -Wall"' stdout
+grep -q '"value": "-Iinclude -Ifoo/include"' stdout
+
+${LAZE} inspect env -b default -a app --module foo --origin > stdout
+grep -q -- '- "-Wall" from module "foo" (laze-project.yml)' stdout
+grep -q -- '- "-Os" from builder "default" (laze-project.yml)' stdout
+
+# errors about variables tell where they were set
+if ${LAZE} inspect env -b broken -a app 2> stderr; then exit 1; fi
+grep -q 'variable "USED" (set by builder "broken" (laze-project.yml)) has both values' stderr
+
+echo TEST_OK
+
+cleanup
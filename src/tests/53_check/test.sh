@@ -0,0 +1,23 @@
+#!/bin/sh
+
+. ../test-common.sh
+
+cleanup
+
+${LAZE} check > stdout
+diff -q EXPECTED_STDOUT stdout
+
+${LAZE} check -a app,blocked_app --json > stdout
+diff -q EXPECTED_STDOUT_JSON stdout
+
+# blocked_app is not built for other_builder anymore
+if ${LAZE} check -a app,blocked_app --baseline baseline.json; then
+    false
+fi
+
+# pairs that are not selected are not compared
+${LAZE} check -a app --baseline baseline.json
+
+echo TEST_OK
+
+cleanup
@@ -0,0 +1,22 @@
+#!/bin/sh
+
+. ../test-common.sh
+
+cleanup
+
+${LAZE} inspect graph -b default -a app > stdout
+diff -q EXPECTED_STDOUT_DOT stdout
+
+${LAZE} inspect graph -b default -a app --format mermaid > stdout
+diff -q EXPECTED_STDOUT_MERMAID stdout
+
+${LAZE} inspect graph -b default -a app --format json > stdout
+diff -q EXPECTED_STDOUT_JSON stdout
+
+if ${LAZE} inspect graph -b default -a nonexistent; then
+    false
+fi
+
+echo TEST_OK
+
+cleanup
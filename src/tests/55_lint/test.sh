@@ -0,0 +1,11 @@
+#!/bin/sh
+
+. ../test-common.sh
+
+cleanup
+
+run_laze lint
+
+echo TEST_OK
+
+cleanup
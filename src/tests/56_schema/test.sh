@@ -0,0 +1,22 @@
+#!/bin/sh
+
+. ../test-common.sh
+
+cleanup
+
+${LAZE} schema > stdout
+
+# spot check a few fields of the different entries
+grep -q '"title": "laze file"' stdout
+grep -q '"is_global_build_dep"' stdout
+grep -q '"provides_unique"' stdout
+grep -q '"required_modules"' stdout
+grep -q '"gcc_deps"' stdout
+
+# aliases
+grep -q '"buildable"' stdout
+grep -q '"disables"' stdout
+
+echo TEST_OK
+
+cleanup
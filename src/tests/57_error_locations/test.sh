@@ -0,0 +1,10 @@
+#!/bin/sh
+
+. ../test-common.sh
+
+cleanup
+build
+
+echo TEST_OK
+
+cleanup
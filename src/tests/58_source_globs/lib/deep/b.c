@@ -0,0 +1 @@
+/* lib/deep/b.c */
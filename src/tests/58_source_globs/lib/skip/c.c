@@ -0,0 +1 @@
+/* lib/skip/c.c */
@@ -0,0 +1,38 @@
+#!/bin/sh
+
+. ../test-common.sh
+
+cleanup
+
+objects() {
+    grep "^build build/objects/.*\.o:" build/build-global.ninja | cut -d' ' -f2
+}
+
+${LAZE} build -g -G
+objects > stdout
+diff -q EXPECTED_OBJECTS stdout
+
+# adding a file to a globbed directory invalidates the cache
+echo "/* new */" > lib/deep/new.c
+${LAZE} build -g -G
+rm lib/deep/new.c
+objects | grep -q "lib/deep/new\."
+
+${LAZE} build -g -G
+objects > stdout
+diff -q EXPECTED_OBJECTS stdout
+
+# so does creating a globbed directory that did not exist yet
+mkdir generated/out
+echo "/* generated */" > generated/out/gen.c
+${LAZE} build -g -G
+rm -r generated/out
+objects | grep -q "generated/out/gen\."
+
+${LAZE} build -g -G
+objects > stdout
+diff -q EXPECTED_OBJECTS stdout
+
+echo TEST_OK
+
+cleanup
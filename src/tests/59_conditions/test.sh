@@ -0,0 +1,21 @@
+#!/bin/sh
+
+. ../test-common.sh
+
+cleanup
+
+# "never", "not_vendored", "missing.yml" and never.c are dropped by their
+# conditions, "always" exports -DVENDOR as its condition sees its own srcdir
+run_laze inspect modules
+
+${LAZE} build -g -G
+grep "^build build/objects/.*\.o:" build/build-global.ninja | cut -d' ' -f2 > stdout
+diff -q EXPECTED_OBJECTS stdout
+
+${LAZE} inspect env -b default -a app --module app > stdout
+grep -q "^CFLAGS = -O2$" stdout
+grep -q "^DEFINES = -DVENDOR -DAPP -DHOST_ARCH$" stdout
+
+echo TEST_OK
+
+cleanup
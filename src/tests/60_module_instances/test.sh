@@ -0,0 +1,23 @@
+#!/bin/sh
+
+. ../test-common.sh
+
+cleanup
+
+# the template "uart" itself is not defined
+run_laze inspect modules
+
+${LAZE} build -g -G
+grep "^build build/objects/.*\.o:" build/build-global.ninja | cut -d' ' -f2 > stdout
+diff -q EXPECTED_OBJECTS stdout
+
+${LAZE} inspect env -b default -a app --module uart1 > stdout
+grep -q "^CFLAGS = -DUART_INSTANCE=uart1$" stdout
+grep -q "^UART_BASE = 0x2000$" stdout
+
+${LAZE} inspect env -b default -a app --module app > stdout
+grep -q "^UARTS = uart0 uart1$" stdout
+
+echo TEST_OK
+
+cleanup
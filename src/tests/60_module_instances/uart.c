@@ -0,0 +1 @@
+/* uart.c */
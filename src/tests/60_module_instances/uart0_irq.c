@@ -0,0 +1 @@
+/* uart0_irq.c */
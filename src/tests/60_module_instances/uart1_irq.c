@@ -0,0 +1 @@
+/* uart1_irq.c */
@@ -0,0 +1 @@
+/* gen/a.c */
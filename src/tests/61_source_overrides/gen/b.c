@@ -0,0 +1 @@
+/* gen/b.c */
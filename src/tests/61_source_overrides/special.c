@@ -0,0 +1 @@
+/* special.c */
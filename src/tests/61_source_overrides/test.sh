@@ -0,0 +1,18 @@
+#!/bin/sh
+
+. ../test-common.sh
+
+cleanup
+
+${LAZE} build -g -G
+grep "^build build/objects/.*\.o:" build/build-global.ninja | cut -d' ' -f2 > stdout
+diff -q EXPECTED_OBJECTS stdout
+
+# per-file env is merged over the module's, the rule can be chosen per file
+grep -q "command = echo -O2 -O0 |" build/build-global.ninja
+grep -q "command = echo -O2 -DGENERATED |" build/build-global.ninja
+grep -q "command = echo special -O2 |" build/build-global.ninja
+
+echo TEST_OK
+
+cleanup
@@ -0,0 +1 @@
+/* parser.gen.c */
@@ -0,0 +1,18 @@
+#!/bin/sh
+
+. ../test-common.sh
+
+cleanup
+
+${LAZE} build -g -G
+
+# object builds and the rule each one uses
+grep -A1 "^build build/objects" build/build-global.ninja | grep -v "^--" > stdout
+diff -q EXPECTED_BUILDS stdout
+
+# table.inc is shared between both apps, as it uses the same rule and env
+grep -A3 "^build /thumb_app.elf:" build/build-global.ninja | grep -q "build/objects/table.10810010563815199638.o"
+
+echo TEST_OK
+
+cleanup
@@ -0,0 +1,27 @@
+#!/bin/sh
+
+. ../test-common.sh
+
+cleanup
+
+${LAZE} build -g -G -a app,special_app
+
+# the exported rule builds parser.y, with ${relpath} of the module defining it
+grep -q "command = gen/yacc.sh" build/build-global.ninja
+grep -A1 "^build build/objects/parser" build/build-global.ninja | grep -q YACC_
+
+# a module's own rule replaces the builder's
+grep -A1 "^build build/objects/main" build/build-global.ninja | grep -q CC_SPECIAL_
+
+# two imported modules exporting rules for the same input are an error
+if ${LAZE} build -g -G -a ambiguous_app 2> stderr; then
+    exit 1
+fi
+grep -q 'module "ambiguous_app" imports rules for "y" from both "gen" and "gen2"' stderr
+
+# lint knows about module rules
+${LAZE} lint | grep -q "^0 errors"
+
+echo TEST_OK
+
+cleanup
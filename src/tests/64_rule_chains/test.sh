@@ -0,0 +1,22 @@
+#!/bin/sh
+
+. ../test-common.sh
+
+cleanup
+
+${LAZE} build -g -G -a app
+
+# msg.proto is turned into msg.pb.c, which is then compiled and linked.
+# the generated source goes to a directory named after its hash, so only the
+# object's name contains a hash.
+grep -A2 "^build build/objects/[0-9]*/msg\.pb\.c:" build/build-global.ninja | grep -q "^    msg.proto"
+grep -A2 "^build build/objects/msg\.pb\.[0-9]*\.o:" build/build-global.ninja | grep -q "^    build/objects/[0-9]*/msg\.pb\.c"
+grep -A4 "^build /app.elf:" build/build-global.ninja | grep -q "build/objects/msg\.pb\.[0-9]*\.o"
+
+# a rule applying to its own output is an error
+${LAZE} build -g -G -a loop 2>stderr && exit 1
+grep -q "more than 8 rules in a row" stderr
+
+echo TEST_OK
+
+cleanup
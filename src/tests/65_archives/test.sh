@@ -0,0 +1,42 @@
+#!/bin/sh
+
+. ../test-common.sh
+
+cleanup
+
+${LAZE} build -g -G -b default
+
+# each archived module gets its own library
+grep -A2 "^build build/objects/liblibfoo\..*\.a:" build/build-global.ninja | grep -q "^    build/objects/foo\..*\.o"
+grep -A2 "^build build/objects/liblibbar\..*\.a:" build/build-global.ninja | grep -q "^    build/objects/bar\..*\.o"
+
+# archives are linked instead of their objects, whole archives using the variable
+grep -A6 "^build /app.elf:" build/build-global.ninja > stdout
+grep -q "liblibfoo" stdout
+grep -q "^  whole_archives = build/objects/liblibbar\..*\.a" stdout
+grep -q "foo\..*\.o" stdout && exit 1
+
+# apps with the same inputs share the archive
+[ "$(grep -c "^build build/objects/liblibfoo" build/build-global.ninja)" = 1 ]
+grep -A4 "^build /other_app.elf:" build/build-global.ninja | grep -q "liblibfoo"
+
+# modules are only archived if they or the builder say so
+grep -A5 "^build /other_app.elf:" build/build-global.ninja | grep -q "plain\..*\.o"
+grep -q "libplain" build/build-global.ninja && exit 1
+${LAZE} build -g -G -b archiving -a other_app
+grep -A5 "^build /other_app.elf:" build/build-global.ninja | grep -q "libplain\..*\.a"
+grep -q "libother_app" build/build-global.ninja && exit 1
+
+# archives come before the archives they depend on, even if those were
+# selected first
+${LAZE} build -g -G -b archiving -a diamond_app
+grep -A6 "^build /diamond_app.elf:" build/build-global.ninja > stdout
+[ "$(grep -n "liblibc\." stdout | cut -d: -f1)" -lt "$(grep -n "liblibb\." stdout | cut -d: -f1)" ]
+
+# whole archives need a LINK rule using them
+${LAZE} build -g -G -b no_whole_archives -a app 2>stderr && exit 1
+grep -q "does not use" stderr
+
+echo TEST_OK
+
+cleanup
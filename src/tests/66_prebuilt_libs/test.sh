@@ -0,0 +1,25 @@
+#!/bin/sh
+
+. ../test-common.sh
+
+cleanup
+
+${LAZE} build -g -G
+
+grep -A7 "^build /app.elf:" build/build-global.ninja > stdout
+
+# libs are linked after the objects, link_deps only trigger relinking
+grep -A3 "^    build/objects/main" stdout | grep -q "^    vendor/lib/libvendor.a"
+grep -A2 "^    |" stdout | grep -q "^    vendor/vendor.ld"
+
+# downloaded libs are created by the download step
+grep -q "^    build/dl/vendor/remote_sdk/libremote.a" stdout
+grep -A3 "^build build/dl/vendor/remote_sdk/libremote.a:" build/build-global.ninja | grep -q "remote_sdk/.laze-downloaded"
+
+# a module's own archive comes before its prebuilt libs, which it uses
+grep -A4 "^build /wrapped_app.elf:" build/build-global.ninja > stdout
+grep -A1 "^    build/objects/libvendor_wrapper\..*\.a" stdout | grep -q "^    vendor/lib/libwrapped.a"
+
+echo TEST_OK
+
+cleanup
@@ -0,0 +1,55 @@
+#!/bin/sh
+
+. ../test-common.sh
+
+cleanup
+rm -f ninja-args info.json
+
+${LAZE} build -g -G -b default,with_hex --info-export info.json
+
+# artifacts are made from the app, or from another artifact
+grep -A2 "^build build/default/app/app.bin:" build/build-global.ninja | grep -q "^    build/default/app/app.elf"
+grep -A2 "^build build/default/app/app.signed.bin:" build/build-global.ninja | grep -q "^    build/default/app/app.bin"
+
+# child contexts add artifacts
+grep -q "^build build/default/app/app.hex:" build/build-global.ninja && exit 1
+grep -q "^build build/with_hex/app/app.hex:" build/build-global.ninja
+
+# artifacts are recorded
+grep -q '"hex": "build/with_hex/app/app.hex"' info.json
+
+# tasks can use artifacts
+${LAZE} build -g -b with_hex flash | grep -q "^flashing build/with_hex/app/app.signed.bin"
+
+# a fake ninja records which targets get built
+PATH="$(pwd):$PATH" ${LAZE} build -g -b with_hex
+grep -q "build/with_hex/app/app.elf .*app.hex" ninja-args
+PATH="$(pwd):$PATH" ${LAZE} build -g -b with_hex --artifacts hex
+grep -q "build/with_hex/app/app.hex$" ninja-args
+grep -q "app.elf" ninja-args && exit 1
+
+# tasks only build the artifacts they use (here through a subtask), or those
+# requested explicitly
+PATH="$(pwd):$PATH" ${LAZE} build -g -b with_hex flash_built > stdout
+grep -q "build/with_hex/app/app.elf build/with_hex/app/app.signed.bin$" ninja-args
+PATH="$(pwd):$PATH" ${LAZE} build -g -b with_hex run > stdout
+grep -q "build/with_hex/app/app.elf$" ninja-args
+PATH="$(pwd):$PATH" ${LAZE} build -g -b with_hex --artifacts hex run > stdout
+grep -q "build/with_hex/app/app.elf build/with_hex/app/app.hex$" ninja-args
+
+# requesting artifacts that don't exist is an error
+${LAZE} build -g -b default --artifacts hex 2>stderr && exit 1
+grep -q "no artifact \"hex\"" stderr
+
+# artifacts must not overwrite each other or the app
+${LAZE} build -g -G -b duplicate_out 2>stderr && exit 1
+grep -q "artifact rule \"OBJCOPY_BIN_AGAIN\": output \"build/duplicate_out/app/app.bin\" is not unique" stderr
+grep -q -- "--> laze-project.yml:45:9" stderr
+${LAZE} build -g -G -b app_out 2>stderr && exit 1
+grep -q "artifact rule \"COPY_ELF\": output \"build/app_out/app/app.elf\" would overwrite the app" stderr
+grep -q -- "--> laze-project.yml:53:9" stderr
+
+echo TEST_OK
+
+cleanup
+rm -f ninja-args info.json
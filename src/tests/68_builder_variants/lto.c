@@ -0,0 +1 @@
+/* lto */
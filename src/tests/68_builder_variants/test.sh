@@ -0,0 +1,42 @@
+#!/bin/sh
+
+. ../test-common.sh
+
+cleanup
+
+# each combination of variant values is a builder, with its own output dir
+${LAZE} build -g -G
+[ "$(grep -c '^build build/board\$:.*/app/app.elf:' build/build-global.ninja)" = 4 ]
+grep -q '^build build/board\$:release\$:lto/app/app.elf:' build/build-global.ninja
+
+# variants' env and selects are applied
+grep -q "command = echo -DDEFAULT -DBOARD -O2 -flto |" build/build-global.ninja
+[ "$(grep -c '^    lto.c' build/build-global.ninja)" = 2 ]
+cleanup
+
+# variants are selected by values
+${LAZE} build -g -G -b board:release
+[ "$(grep -c '^build build/board\$:.*/app/app.elf:' build/build-global.ninja)" = 2 ]
+grep -q '^build build/board\$:debug' build/build-global.ninja && exit 1
+cleanup
+
+${LAZE} build -g -b board:release:lto info > stdout
+grep -q "^board:release:lto release lto -DDEFAULT -DBOARD -O2 -flto$" stdout
+cleanup
+
+# builder and context defaults are inherited by subdirectories
+${LAZE} build -g -b other info > stdout
+grep -q "^other -DFAMILY -DDEFAULT$" stdout
+cleanup
+
+# builders get the defaults only once, not again from each parent
+${LAZE} build -g -b child info > stdout
+grep -q "^child -DDEFAULT -DBASE -DCHILD$" stdout
+cleanup
+
+${LAZE} build -g -b board:fast -G 2>stderr && exit 1
+grep -q "unknown builder" stderr
+
+echo TEST_OK
+
+cleanup
@@ -0,0 +1,60 @@
+#!/bin/sh
+
+. ../test-common.sh
+
+cleanup
+rm -Rf testrepo gitcache laze.lock
+
+export GIT_CACHE_DIR="$(pwd)/gitcache"
+
+commit_version() {
+    echo "$1" > testrepo/version
+    git -C testrepo add .
+    git -C testrepo -c user.name=laze -c user.email=laze@localhost commit -q -m "version $1"
+    git -C testrepo rev-parse HEAD
+}
+
+imported_version() {
+    cat build/imports/testrepo-*/version
+}
+
+git init -q -b main testrepo
+echo "modules: [{ name: imported }]" > testrepo/laze.yml
+COMMIT_1="$(commit_version 1)"
+
+# first load locks the current commit
+${LAZE} build -G
+grep -q "commit: ${COMMIT_1}" laze.lock
+test "$(imported_version)" = 1
+
+# the branch moves on, but the locked commit is checked out
+COMMIT_2="$(commit_version 2)"
+rm -Rf build
+${LAZE} build -G
+test "$(imported_version)" = 1
+${LAZE} --locked build -G
+
+# unknown imports cannot be updated
+if ${LAZE} update nonexistent; then exit 1; fi
+
+${LAZE} update testrepo
+grep -q "commit: ${COMMIT_2}" laze.lock
+test "$(imported_version)" = 2
+
+# checkouts from before imports were locked get redone and locked
+rm laze.lock
+TAGFILE="$(echo build/imports/testrepo-*/.laze-downloaded)"
+echo '{"git":{"url":"./testrepo","branch":"main"}}' > "${TAGFILE}"
+${LAZE} build -G
+grep -q "commit: ${COMMIT_2}" laze.lock
+grep -q "${COMMIT_2}" "${TAGFILE}"
+
+# --locked fails without laze.lock entry
+rm laze.lock
+if ${LAZE} --locked build -G; then exit 1; fi
+test ! -f laze.lock
+
+cleanup
+rm -Rf testrepo gitcache laze.lock
+
+echo TEST_OK
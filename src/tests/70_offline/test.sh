@@ -0,0 +1,90 @@
+#!/bin/sh
+
+. ../test-common.sh
+
+cleanup
+rm -Rf testrepo laze-local.yml gitcache
+
+git init -q testrepo
+echo "modules: [{ name: imported }]" > testrepo/laze.yml
+echo "int foo;" > testrepo/foo.c
+git -C testrepo add .
+git -C testrepo -c user.name=laze -c user.email=laze@localhost commit -q -m "initial"
+COMMIT="$(git -C testrepo rev-parse HEAD)"
+URL="file://$(pwd)/testrepo"
+
+cat > laze-local.yml <<EOT
+imports:
+  - git:
+      url: ${URL}
+      commit: ${COMMIT}
+
+modules:
+  - name: downloaded
+    download:
+      git:
+        url: ${URL}
+        commit: ${COMMIT}
+    sources:
+      - foo.c
+EOT
+
+# nothing was fetched yet, so offline mode fails listing the import
+if ${LAZE} --offline build -G 2> stderr; then exit 1; fi
+grep -q "import git ${URL}@${COMMIT}" stderr
+
+# fetch the import
+${LAZE} build -G
+
+# the import is there now, but the module download is still missing
+if LAZE_OFFLINE=true ${LAZE} build -G 2> stderr; then exit 1; fi
+grep -q "module \"downloaded\": git ${URL}@${COMMIT}" stderr
+
+# (pretend to) have downloaded another commit, which doesn't count
+mkdir -p build/dl/downloaded
+echo 0000000000000000000000000000000000000000 > build/dl/downloaded/.laze-downloaded
+if ${LAZE} --offline build -G 2> stderr; then exit 1; fi
+grep -q "module \"downloaded\": git ${URL}@${COMMIT}" stderr
+
+# (pretend to) have run the download
+echo ${COMMIT} > build/dl/downloaded/.laze-downloaded
+
+${LAZE} --offline build -G
+
+# now with a remote url, which the git cache mirrors. git is told to fetch it
+# from the local repository instead.
+cleanup
+REMOTE_URL="https://laze.invalid/testrepo"
+export GIT_CACHE_DIR="$(pwd)/gitcache"
+export GIT_CONFIG_COUNT=1
+export GIT_CONFIG_KEY_0="url.${URL}.insteadOf"
+export GIT_CONFIG_VALUE_0="${REMOTE_URL}"
+sed -i "s|${URL}|${REMOTE_URL}|" laze-local.yml
+
+# the git cache is still empty
+if ${LAZE} --offline build -G 2> stderr; then exit 1; fi
+grep -q "import git ${REMOTE_URL}@${COMMIT}" stderr
+
+# ninja gets LAZE_OFFLINE passed, so the download rule's clone refuses, too
+if LAZE_OFFLINE=true ${LAZE} git-clone --commit ${COMMIT} ${REMOTE_URL} clone 2> stderr; then exit 1; fi
+grep -q "git ${REMOTE_URL}@${COMMIT} is not available offline" stderr
+test ! -e clone
+
+# fetching the import primes the git cache
+${LAZE} build -G
+test -d gitcache/laze.invalid/testrepo.git
+
+# now cloning works offline
+LAZE_OFFLINE=true ${LAZE} git-clone --commit ${COMMIT} ${REMOTE_URL} clone 2> stderr
+test -f clone/foo.c
+rm -Rf clone
+
+# both the import and the module download can now come from the git cache
+rm -Rf build
+${LAZE} --offline build -G
+test -f build/imports/*/laze.yml
+
+cleanup
+rm -Rf testrepo laze-local.yml gitcache
+
+echo TEST_OK
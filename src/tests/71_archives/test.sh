@@ -0,0 +1,74 @@
+#!/bin/sh
+
+. ../test-common.sh
+
+cleanup
+rm -Rf pkg archive-cache sdk.tar.gz vendor.zip vendor-download laze-local.yml extracted extracted3
+
+export LAZE_ARCHIVE_CACHE_DIR="$(pwd)/archive-cache"
+
+# create a tarball to import (with a top-level directory to strip) ...
+mkdir -p pkg/sdk-1.0 pkg/vendor-2.0
+echo "modules: [{ name: sdk, sources: [sdk.c] }]" > pkg/sdk-1.0/laze.yml
+echo "int sdk;" > pkg/sdk-1.0/sdk.c
+tar -czf sdk.tar.gz -C pkg sdk-1.0
+SDK_SHA256="$(sha256sum sdk.tar.gz | cut -d' ' -f1)"
+
+# ... and a zip file to download
+echo "int vendor;" > pkg/vendor-2.0/vendor.c
+(cd pkg && zip -q -r ../vendor.zip vendor-2.0)
+VENDOR_SHA256="$(sha256sum vendor.zip | cut -d' ' -f1)"
+
+cat > laze-local.yml <<EOT
+imports:
+  - archive:
+      url: file://$(pwd)/sdk.tar.gz
+      sha256: ${SDK_SHA256}
+      strip_components: 1
+
+modules:
+  - name: vendored
+    download:
+      archive:
+        url: file://$(pwd)/vendor.zip
+        sha256: ${VENDOR_SHA256}
+        strip_components: 1
+    sources:
+      - vendor.c
+EOT
+
+${LAZE} build -G -b single_builder
+
+# the import was verified, cached and extracted
+test -f "archive-cache/${SDK_SHA256}/sdk.tar.gz"
+grep -q "int sdk;" build/imports/sdk.tar.gz-*/sdk.c
+grep -q "ARCHIVE_DOWNLOAD" build/build-local.ninja
+grep -q "sha256 = ${VENDOR_SHA256}" build/build-local.ninja
+
+# the module download step
+${LAZE} download-archive --sha256 "${VENDOR_SHA256}" --strip-components 1 "file://$(pwd)/vendor.zip" extracted
+grep -q "int vendor;" extracted/vendor.c
+
+# zip files are recognized without the .zip suffix
+cp vendor.zip vendor-download
+${LAZE} download-archive --sha256 "${VENDOR_SHA256}" "file://$(pwd)/vendor-download" extracted3
+grep -q "int vendor;" extracted3/vendor-2.0/vendor.c
+
+# a builder without download rules cannot build modules with downloads
+if ${LAZE} build -G -b no_download_rules 2> stderr; then exit 1; fi
+grep -q 'module "vendored": no ARCHIVE_DOWNLOAD rule available' stderr
+grep -q -- "--> laze-local.yml:10:7" stderr
+
+# wrong checksums are rejected
+if ${LAZE} download-archive --sha256 0000 "file://$(pwd)/vendor.zip" extracted2 2> stderr; then exit 1; fi
+grep -q "checksum mismatch" stderr
+test ! -d extracted2
+
+# cached archives are available offline
+rm -Rf build
+${LAZE} --offline build -G -b single_builder
+
+cleanup
+rm -Rf pkg archive-cache sdk.tar.gz vendor.zip vendor-download laze-local.yml extracted extracted3
+
+echo TEST_OK
@@ -0,0 +1,53 @@
+#!/bin/sh
+
+. ../test-common.sh
+
+cleanup
+rm -Rf testrepo laze.lock
+
+commit() {
+    echo "int foo = $1;" > testrepo/foo.c
+    git -C testrepo add .
+    git -C testrepo -c user.name=laze -c user.email=laze@localhost commit -q -m "version $1"
+    git -C testrepo rev-parse HEAD
+}
+
+count_commit() {
+    grep -c "commit = $1" build/build-local.ninja || true
+}
+
+git init -q -b main testrepo
+COMMIT_1="$(commit 1)"
+git -C testrepo -c user.name=laze -c user.email=laze@localhost tag -a v1 -m v1
+
+# branch, tag and default branch are resolved to the current commit
+# (the patch step of "on_default" uses the commit, too)
+${LAZE} build -G
+test "$(count_commit "${COMMIT_1}")" = 4
+grep -q "GIT_PATCH" build/build-local.ninja
+test "$(grep -c "commit: ${COMMIT_1}" laze.lock)" = 3
+
+# downloads of modules no build uses are not resolved
+grep -q "nonexistent" laze.lock && exit 1
+
+# ... but fail the builds that use them
+if ${LAZE} build -G -s unreachable 2> stderr; then exit 1; fi
+grep -q "resolving downloads failed" stderr
+grep -q "module \"unreachable\"" stderr
+
+# ... and stay locked when the branch moves on
+COMMIT_2="$(commit 2)"
+rm -Rf build
+${LAZE} build -G
+test "$(count_commit "${COMMIT_1}")" = 4
+
+# after updating, only the tag still points to the old commit
+${LAZE} update testrepo
+${LAZE} build -G
+test "$(count_commit "${COMMIT_1}")" = 1
+test "$(count_commit "${COMMIT_2}")" = 3
+
+cleanup
+rm -Rf testrepo laze.lock stderr
+
+echo TEST_OK
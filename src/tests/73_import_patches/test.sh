@@ -0,0 +1,49 @@
+#!/bin/sh
+
+. ../test-common.sh
+
+cleanup
+rm -Rf testrepo laze.lock local.patch
+
+write_patch() {
+    cat > local.patch <<EOT
+--- a/foo.c
++++ b/foo.c
+@@ -1 +1 @@
+-int foo = 1;
++int foo = $1;
+EOT
+}
+
+git init -q -b main testrepo
+echo "modules: [{ name: foo, sources: [foo.c] }]" > testrepo/laze.yml
+echo "int foo = 1;" > testrepo/foo.c
+git -C testrepo add .
+git -C testrepo -c user.name=laze -c user.email=laze@localhost commit -q -m "initial"
+
+# the patch gets applied after cloning
+write_patch 2
+${LAZE} build -G
+IMPORT="$(echo build/imports/testrepo-*)"
+grep -q "int foo = 2;" "${IMPORT}/foo.c"
+
+# unchanged patches keep the checkout
+touch "${IMPORT}/marker"
+${LAZE} build -G
+test -f "${IMPORT}/marker"
+
+# changed patches lead to a fresh checkout
+write_patch 3
+${LAZE} build -G
+test ! -f "${IMPORT}/marker"
+grep -q "int foo = 3;" "${IMPORT}/foo.c"
+
+# patches that don't apply are an error
+sed -i 's/int foo = 1;/int foo = 4;/' local.patch
+if ${LAZE} build -G 2> stderr; then exit 1; fi
+grep -q "applying patch" stderr
+
+cleanup
+rm -Rf testrepo laze.lock local.patch stderr
+
+echo TEST_OK
@@ -15,12 +15,16 @@ diff_build_dir() {
 }
 
 build() {
+    run_laze build -g "$@"
+}
+
+run_laze() {
     if [ -f EXPECTED_EXIT_CODE ]; then
         # ignore actual exit code
         set +e
     fi
 
-    ${LAZE} build -g "$@" > stdout 2> stderr
+    ${LAZE} "$@" > stdout 2> stderr
     EXIT_CODE=$?
 
     if [ -f EXPECTED_EXIT_CODE ]; then
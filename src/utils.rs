@@ -3,6 +3,7 @@ use std::hash::{Hash, Hasher};
 
 use camino::Utf8PathBuf;
 use indexmap::IndexMap;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use crate::model::VarExportSpec;
@@ -13,14 +14,34 @@ pub(crate) fn calculate_hash<T: Hash>(t: &T) -> u64 {
     s.finish()
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, JsonSchema)]
 #[serde(untagged)]
 pub enum StringOrMapVecString {
     String(String),
+    If(Conditional<StringOrMapVecString>),
     Map(std::collections::HashMap<String, Vec<String>>),
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, JsonSchema)]
+#[serde(untagged)]
+pub enum StringOrConditional {
+    String(String),
+    If(Conditional<String>),
+}
+
+/// List entries that are only used if a condition holds
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, JsonSchema)]
+#[serde(deny_unknown_fields)]
+#[schemars(rename = "Conditional_{T}")]
+pub struct Conditional<T> {
+    /// Expression that needs to evaluate to `true`, e.g., `${host::os} == "linux"`.
+    #[serde(rename = "if")]
+    pub condition: String,
+    /// Entries to use if the condition is `true`.
+    pub then: Vec<T>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, JsonSchema)]
 #[serde(untagged)]
 pub enum StringOrMapString {
     String(String),